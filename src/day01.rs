@@ -1,5 +1,5 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use eyre::Result;
+use std::collections::{BTreeSet, HashMap};
 
 #[aoc_generator(day1)]
 fn parse_input_day1(input: &str) -> Vec<i32> {
@@ -11,9 +11,7 @@ fn parse_input_day1(input: &str) -> Vec<i32> {
 
 #[aoc(day1, part1)]
 fn solve_part1(input: &[i32]) -> i32 {
-    let mut input = input.to_owned();
-    input.sort_unstable();
-    find_pair(input.as_slice(), 2020)
+    find_pair(input, 2020)
         .expect("Expect a pair")
         .iter()
         .product()
@@ -21,47 +19,183 @@ fn solve_part1(input: &[i32]) -> i32 {
 
 #[aoc(day1, part2)]
 fn solve_part2(input: &[i32]) -> i32 {
-    let mut input = input.to_owned();
-    input.sort_unstable();
-    find_tripple(input.as_slice())
+    find_tripple(input, 2020)
         .expect("Expect a tripple")
         .iter()
         .product()
 }
 
-fn find_pair(list: &[i32], target: i32) -> Option<[i32; 2]> {
-    let mut head = 0;
-    let mut tail = list.len() - 1;
-    loop {
-        if head >= tail {
-            return None;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reuse {
+    /// Every entry of the list can be used at most once.
+    Distinct,
+    /// The same entry can be picked several times.
+    Allow,
+}
+
+/// Every combination of `k` entries of `list` that sums to `target`, each sorted ascending.
+pub fn k_sum(list: &[i32], k: usize, target: i32, reuse: Reuse) -> Vec<Vec<i32>> {
+    search(list, k, target, reuse, false)
+}
+
+/// The first combination `k_sum` would find, without looking for the rest.
+pub fn k_sum_first(list: &[i32], k: usize, target: i32, reuse: Reuse) -> Option<Vec<i32>> {
+    search(list, k, target, reuse, true).into_iter().next()
+}
+
+fn search(list: &[i32], k: usize, target: i32, reuse: Reuse, first: bool) -> Vec<Vec<i32>> {
+    let mut sorted = list.to_owned();
+    sorted.sort_unstable();
+    match k {
+        0 if target == 0 => vec![vec![]],
+        0 => vec![],
+        1 => sorted
+            .binary_search(&target)
+            .map(|_| vec![vec![target]])
+            .unwrap_or_default(),
+        2 => hashed_pairs(&sorted, target, reuse, first),
+        3 => two_pointer_triples(&sorted, target, reuse, first),
+        _ => meet_in_the_middle(&sorted, k, target, reuse, first),
+    }
+}
+
+fn hashed_pairs(sorted: &[i32], target: i32, reuse: Reuse, first: bool) -> Vec<Vec<i32>> {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for v in sorted {
+        *counts.entry(*v).or_insert(0) += 1;
+    }
+    let needed = |a: i32, b: i32| match (a == b, reuse) {
+        (true, Reuse::Distinct) => 2,
+        _ => 1,
+    };
+    let mut found = Vec::new();
+    let mut values: Vec<_> = counts.keys().cloned().collect();
+    values.sort_unstable();
+    for a in values {
+        let b = target - a;
+        if b < a {
+            break;
         }
-        let sum = list.get(head).expect("Should work") + list.get(tail).expect("should work");
-        match sum {
-            sum if sum > target => {
-                tail -= 1;
+        if counts.get(&b).is_some_and(|c| *c >= needed(a, b)) {
+            found.push(vec![a, b]);
+            if first {
+                break;
             }
-            sum if sum < target => {
-                tail += 1;
+        }
+    }
+    found
+}
+
+fn two_pointer_triples(sorted: &[i32], target: i32, reuse: Reuse, first: bool) -> Vec<Vec<i32>> {
+    let mut found = Vec::new();
+    let (offset, same_entry) = match reuse {
+        Reuse::Distinct => (1, false),
+        Reuse::Allow => (0, true),
+    };
+    for i in 0..sorted.len() {
+        if i > 0 && sorted[i] == sorted[i - 1] {
+            continue;
+        }
+        let rest = target - sorted[i];
+        let mut head = i + offset;
+        let mut tail = sorted.len() - 1;
+        while head < sorted.len() && (head < tail || (same_entry && head == tail)) {
+            let sum = sorted[head] + sorted[tail];
+            if sum > rest {
+                if tail == 0 {
+                    break;
+                }
+                tail -= 1;
+            } else if sum < rest {
                 head += 1;
-            }
-            _ => {
-                break Some([
-                    *list.get(head).expect("already got this"),
-                    *list.get(tail).expect("already got this"),
-                ]);
+            } else {
+                found.push(vec![sorted[i], sorted[head], sorted[tail]]);
+                if first {
+                    return found;
+                }
+                let (h, t) = (sorted[head], sorted[tail]);
+                while head < sorted.len() && sorted[head] == h {
+                    head += 1;
+                }
+                while tail > 0 && sorted[tail] == t {
+                    tail -= 1;
+                }
             }
         }
     }
+    found
+}
+
+// Index combinations of `size`, strictly increasing unless entries can be reused.
+fn combinations(len: usize, size: usize, reuse: Reuse) -> Vec<Vec<usize>> {
+    let mut result = vec![vec![]];
+    for _ in 0..size {
+        result = result
+            .into_iter()
+            .flat_map(|combo: Vec<usize>| {
+                let start = match (combo.last(), reuse) {
+                    (None, _) => 0,
+                    (Some(l), Reuse::Distinct) => l + 1,
+                    (Some(l), Reuse::Allow) => *l,
+                };
+                (start..len).map(move |i| {
+                    let mut next = combo.clone();
+                    next.push(i);
+                    next
+                })
+            })
+            .collect();
+    }
+    result
 }
 
-fn find_tripple(list: &[i32]) -> Option<[i32; 3]> {
-    for i in 0..(list.len() - 2) {
-        if let Some(p) = find_pair(&list[i..], 2020 - list.get(i)?) {
-            return Some([*list.get(i)?, p[0], p[1]]);
+fn meet_in_the_middle(
+    sorted: &[i32],
+    k: usize,
+    target: i32,
+    reuse: Reuse,
+    first: bool,
+) -> Vec<Vec<i32>> {
+    let low = k / 2;
+    let mut high_sums: HashMap<i32, Vec<Vec<usize>>> = HashMap::new();
+    for combo in combinations(sorted.len(), k - low, reuse) {
+        let sum = combo.iter().map(|i| sorted[*i]).sum();
+        high_sums.entry(sum).or_default().push(combo);
+    }
+
+    // Each index set is only built once: the low half takes its smallest indices.
+    let fits = |low: &[usize], high: &[usize]| match (low.last(), reuse) {
+        (None, _) => true,
+        (Some(l), Reuse::Distinct) => *l < high[0],
+        (Some(l), Reuse::Allow) => *l <= high[0],
+    };
+    let mut found = BTreeSet::new();
+    for combo in combinations(sorted.len(), low, reuse) {
+        let sum: i32 = combo.iter().map(|i| sorted[*i]).sum();
+        if let Some(highs) = high_sums.get(&(target - sum)) {
+            for high in highs.iter().filter(|h| fits(&combo, h)) {
+                found.insert(
+                    combo
+                        .iter()
+                        .chain(high.iter())
+                        .map(|i| sorted[*i])
+                        .collect::<Vec<_>>(),
+                );
+                if first {
+                    return found.into_iter().collect();
+                }
+            }
         }
     }
-    None
+    found.into_iter().collect()
+}
+
+fn find_pair(list: &[i32], target: i32) -> Option<[i32; 2]> {
+    k_sum_first(list, 2, target, Reuse::Distinct).map(|p| [p[0], p[1]])
+}
+
+fn find_tripple(list: &[i32], target: i32) -> Option<[i32; 3]> {
+    k_sum_first(list, 3, target, Reuse::Distinct).map(|t| [t[0], t[1], t[2]])
 }
 
 #[cfg(test)]
@@ -85,9 +219,66 @@ mod test {
     fn tripple() -> Result<()> {
         let mut tdata = vec![1721, 979, 366, 299, 675, 1456];
         tdata.sort_unstable();
-        let tripple = find_tripple(tdata.as_slice()).ok_or_else(|| anyhow!("No Tripple"))?;
+        let tripple = find_tripple(tdata.as_slice(), 2020).ok_or_else(|| anyhow!("No Tripple"))?;
         let mult: i32 = Vec::from(tripple).iter().product();
         assert_eq!(mult, 241861950);
         Ok(())
     }
+
+    #[test]
+    fn pair_past_the_end() {
+        // The old two-pointer stepped the tail forward and ran off the end here.
+        assert_eq!(find_pair(&[1, 2, 3], 100), None);
+        assert_eq!(find_pair(&[1, 2, 3], 5), Some([2, 3]));
+    }
+
+    // Reference answer by trying every index combination.
+    fn brute_force(list: &[i32], k: usize, target: i32, reuse: Reuse) -> Vec<Vec<i32>> {
+        let mut sorted = list.to_owned();
+        sorted.sort_unstable();
+        let found: BTreeSet<Vec<i32>> = combinations(sorted.len(), k, reuse)
+            .into_iter()
+            .map(|c| c.iter().map(|i| sorted[*i]).collect::<Vec<_>>())
+            .filter(|c| c.iter().sum::<i32>() == target)
+            .collect();
+        found.into_iter().collect()
+    }
+
+    #[test]
+    fn k_sum_matches_brute_force() {
+        let list = [3, -1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 0];
+        for k in 0..=5 {
+            for target in -2..=25 {
+                for reuse in &[Reuse::Distinct, Reuse::Allow] {
+                    let mut found = k_sum(&list, k, target, *reuse);
+                    found.sort();
+                    assert_eq!(
+                        found,
+                        brute_force(&list, k, target, *reuse),
+                        "k={} target={} {:?}",
+                        k,
+                        target,
+                        reuse
+                    );
+                    assert_eq!(
+                        k_sum_first(&list, k, target, *reuse).is_some(),
+                        !found.is_empty()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reuse() {
+        assert_eq!(k_sum(&[1010, 5], 2, 2020, Reuse::Distinct).len(), 0);
+        assert_eq!(
+            k_sum(&[1010, 5], 2, 2020, Reuse::Allow),
+            vec![vec![1010, 1010]]
+        );
+        assert_eq!(
+            k_sum(&[1010, 1010, 5], 2, 2020, Reuse::Distinct),
+            vec![vec![1010, 1010]]
+        );
+    }
 }
@@ -1,44 +1,234 @@
-use std::str::FromStr;
+use std::{fmt, num::ParseIntError, str::FromStr};
 
-use eyre::{eyre, ContextCompat, Error, Result};
+use eyre::{eyre, Error, Result, WrapErr};
 
+// Positions and counts are in chars, never bytes.
 #[derive(Debug, Eq, PartialEq)]
 pub struct PasswordEntry {
-    min: u8,
-    max: u8,
+    min: usize,
+    max: usize,
     letter: char,
     password: String,
 }
 
 impl PasswordEntry {
     fn is_valid(&self) -> bool {
-        let len: u8 = self.password.chars().filter(|c| c == &self.letter).count() as u8;
-
-        len >= self.min && len <= self.max
+        CountPolicy.is_valid(self)
     }
 
     fn is_valid_2(&self) -> bool {
-        let s = self.password.as_bytes(); //assumption that we aren't using utf8 for this, based on glancing at input
-        let min = s.get((self.min - 1) as usize);
-        let max = s.get((self.max - 1) as usize);
-
-        let l_byte: u8 = {
-            let s = self.letter.to_string();
-            *s.as_bytes()
-                .get(0)
-                .expect("Assuming that we are only dealing with ASCII")
+        PositionPolicy.is_valid(self)
+    }
+}
+
+/// Why an entry failed a policy, one reason per rule that didn't hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub reasons: Vec<String>,
+}
+
+impl Violation {
+    fn new(reason: String) -> Self {
+        Violation {
+            reasons: vec![reason],
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reasons.join("; "))
+    }
+}
+
+pub trait Policy {
+    fn check(&self, entry: &PasswordEntry) -> Result<(), Violation>;
+
+    fn is_valid(&self, entry: &PasswordEntry) -> bool {
+        self.check(entry).is_ok()
+    }
+}
+
+/// The letter has to show up between `min` and `max` times.
+pub struct CountPolicy;
+
+impl Policy for CountPolicy {
+    fn check(&self, entry: &PasswordEntry) -> Result<(), Violation> {
+        let count = entry
+            .password
+            .chars()
+            .filter(|c| c == &entry.letter)
+            .count();
+        if count >= entry.min && count <= entry.max {
+            Ok(())
+        } else {
+            Err(Violation::new(format!(
+                "{:?} appears {} times, expected {}-{}",
+                entry.letter, count, entry.min, entry.max
+            )))
+        }
+    }
+}
+
+/// The letter has to be at exactly one of the (1 based) positions `min` and `max`.
+pub struct PositionPolicy;
+
+impl Policy for PositionPolicy {
+    fn check(&self, entry: &PasswordEntry) -> Result<(), Violation> {
+        let at = |pos: usize| {
+            pos.checked_sub(1)
+                .and_then(|i| entry.password.chars().nth(i))
+                == Some(entry.letter)
         };
+        match (at(entry.min), at(entry.max)) {
+            (true, false) | (false, true) => Ok(()),
+            (true, true) => Err(Violation::new(format!(
+                "{:?} is at both positions {} and {}",
+                entry.letter, entry.min, entry.max
+            ))),
+            (false, false) => Err(Violation::new(format!(
+                "{:?} is at neither position {} nor {}",
+                entry.letter, entry.min, entry.max
+            ))),
+        }
+    }
+}
 
-        match (min, max) {
-            (Some(min), Some(max)) => (min == &l_byte) ^ (max == &l_byte),
-            _ => false,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharClass::Lower => c.is_lowercase(),
+            CharClass::Upper => c.is_uppercase(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
         }
     }
 }
 
-impl FromStr for PasswordEntry {
+/// A policy as written in a config file, e.g. `count and (min length 8 or no repeats)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    Count,
+    Positions,
+    MinLength(usize),
+    Contains(CharClass),
+    NoRepeats,
+    All(Vec<Rule>),
+    Any(Vec<Rule>),
+}
+
+impl Policy for Rule {
+    fn check(&self, entry: &PasswordEntry) -> Result<(), Violation> {
+        match self {
+            Rule::Count => CountPolicy.check(entry),
+            Rule::Positions => PositionPolicy.check(entry),
+            Rule::MinLength(min) => {
+                let len = entry.password.chars().count();
+                if len >= *min {
+                    Ok(())
+                } else {
+                    Err(Violation::new(format!(
+                        "password is {} long, expected at least {}",
+                        len, min
+                    )))
+                }
+            }
+            Rule::Contains(class) => {
+                if entry.password.chars().any(|c| class.matches(c)) {
+                    Ok(())
+                } else {
+                    Err(Violation::new(format!("no {:?} character", class)))
+                }
+            }
+            Rule::NoRepeats => {
+                let chars: Vec<_> = entry.password.chars().collect();
+                match chars.windows(2).position(|w| w[0] == w[1]) {
+                    None => Ok(()),
+                    Some(i) => Err(Violation::new(format!(
+                        "{:?} repeats at position {}",
+                        chars[i],
+                        i + 2
+                    ))),
+                }
+            }
+            Rule::All(rules) => {
+                let reasons: Vec<_> = rules
+                    .iter()
+                    .filter_map(|r| r.check(entry).err())
+                    .flat_map(|v| v.reasons)
+                    .collect();
+                if reasons.is_empty() {
+                    Ok(())
+                } else {
+                    Err(Violation { reasons })
+                }
+            }
+            Rule::Any(rules) => {
+                let mut reasons = Vec::new();
+                for r in rules {
+                    match r.check(entry) {
+                        Ok(()) => return Ok(()),
+                        Err(v) => reasons.extend(v.reasons),
+                    }
+                }
+                Err(Violation { reasons })
+            }
+        }
+    }
+}
+
+impl FromStr for Rule {
     type Err = Error;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        nom_parser::rule(s)
+    }
+}
+
+/// Reads named policies from a config file, see `nom_parser::policies` for the format.
+pub fn parse_policies(config: &str) -> Result<Vec<(String, Rule)>> {
+    nom_parser::policies(config)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Range,
+    Bound(ParseIntError),
+    Letter,
+    Password,
+}
+
+/// Where in the line an entry stopped parsing, `offset` in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::Range => write!(f, "expected a min-max range at {}", self.offset),
+            ParseErrorKind::Bound(e) => write!(f, "bad range bound at {}: {}", self.offset, e),
+            ParseErrorKind::Letter => write!(f, "expected a letter and ':' at {}", self.offset),
+            ParseErrorKind::Password => write!(f, "expected a password at {}", self.offset),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for PasswordEntry {
+    type Err = ParseError;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         nom_parser::parse(s)
     }
@@ -47,41 +237,63 @@ impl FromStr for PasswordEntry {
 mod nom_parser {
     use super::*;
     use nom::{
+        branch::alt,
         bytes::complete::tag,
         character::complete::char,
-        character::complete::{alpha1, anychar, multispace0, one_of},
-        combinator::recognize,
+        character::complete::{
+            alphanumeric1, anychar, digit1, multispace0, one_of, satisfy, space0, space1,
+        },
+        combinator::{all_consuming, map, map_res, recognize, value},
         multi::many0,
         multi::many1,
-        sequence::{separated_pair, terminated},
+        multi::separated_list1,
+        sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
         IResult,
     };
 
-    pub(crate) fn parse(s: &str) -> Result<PasswordEntry> {
-        let (s, (min, max)) = range(s).map_err(|e| eyre!("{}", e))?;
-        let (s, letter) = l(s).map_err(|e| eyre!("{}", e))?;
-        let (_, password) = pw(s).map_err(|e| eyre!("{}", e))?;
+    pub(crate) fn parse(line: &str) -> Result<PasswordEntry, ParseError> {
+        // Every slice nom hands back points into `line`.
+        let error_at = |rest: &str, kind| ParseError {
+            offset: rest.as_ptr() as usize - line.as_ptr() as usize,
+            kind,
+        };
+        let bound = |digits: &str| {
+            digits
+                .replace('_', "")
+                .parse::<usize>()
+                .map_err(|e| error_at(digits, ParseErrorKind::Bound(e)))
+        };
 
-        let letter = letter
-            .chars()
-            .next()
-            .context("Should have a character here")?;
-        let password = password.to_string();
+        let (s, (min, max)) =
+            range(line).map_err(|e| error_at(failed_at(e, line), ParseErrorKind::Range))?;
+        let (s, letter) = l(s).map_err(|e| error_at(failed_at(e, s), ParseErrorKind::Letter))?;
+        let (_, password) =
+            pw(s).map_err(|e| error_at(failed_at(e, s), ParseErrorKind::Password))?;
 
         Ok(PasswordEntry {
-            min: min.parse()?,
-            max: max.parse()?,
+            min: bound(min)?,
+            max: bound(max)?,
             letter,
-            password,
+            password: password.to_string(),
         })
     }
 
+    fn failed_at<'a>(e: nom::Err<nom::error::Error<&'a str>>, input: &'a str) -> &'a str {
+        match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.input,
+            nom::Err::Incomplete(_) => &input[input.len()..],
+        }
+    }
+
     fn pw(s: &str) -> IResult<&str, &str> {
         terminated(recognize(many1(anychar)), multispace0)(s)
     }
 
-    fn l(s: &str) -> IResult<&str, &str> {
-        terminated(terminated(alpha1, tag(":")), multispace0)(s)
+    fn l(s: &str) -> IResult<&str, char> {
+        terminated(
+            terminated(satisfy(|c| !c.is_whitespace()), tag(":")),
+            multispace0,
+        )(s)
     }
 
     fn range(s: &str) -> IResult<&str, (&str, &str)> {
@@ -91,13 +303,89 @@ mod nom_parser {
     fn decimal(input: &str) -> IResult<&str, &str> {
         recognize(many1(terminated(one_of("0123456789"), many0(char('_')))))(input)
     }
+
+    pub(crate) fn rule(s: &str) -> Result<Rule> {
+        let (_, rule) =
+            all_consuming(delimited(space0, any, space0))(s).map_err(|e| eyre!("{}", e))?;
+        Ok(rule)
+    }
+
+    /// One `name = rule` per line, blank lines and `#` comments are skipped.
+    pub(crate) fn policies(s: &str) -> Result<Vec<(String, Rule)>> {
+        s.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (_, (name, rule)) = all_consuming(separated_pair(
+                    recognize(many1(alt((alphanumeric1, tag("_"), tag("-"))))),
+                    tuple((space0, char('='), space0)),
+                    any,
+                ))(line)
+                .map_err(|e| eyre!("{}: {}", line, e))?;
+                Ok((name.to_string(), rule))
+            })
+            .collect()
+    }
+
+    fn any(s: &str) -> IResult<&str, Rule> {
+        map(
+            separated_list1(tuple((space1, tag("or"), space1)), all),
+            |mut rules| match rules.len() {
+                1 => rules.remove(0),
+                _ => Rule::Any(rules),
+            },
+        )(s)
+    }
+
+    fn all(s: &str) -> IResult<&str, Rule> {
+        map(
+            separated_list1(tuple((space1, tag("and"), space1)), term),
+            |mut rules| match rules.len() {
+                1 => rules.remove(0),
+                _ => Rule::All(rules),
+            },
+        )(s)
+    }
+
+    fn term(s: &str) -> IResult<&str, Rule> {
+        alt((
+            delimited(pair(char('('), space0), any, pair(space0, char(')'))),
+            value(Rule::Count, tag("count")),
+            value(Rule::Positions, tag("positions")),
+            value(Rule::NoRepeats, tag("no repeats")),
+            map(
+                preceded(
+                    pair(tag("min length"), space1),
+                    map_res(digit1, |d: &str| d.parse()),
+                ),
+                Rule::MinLength,
+            ),
+            map(
+                preceded(pair(tag("contains"), space1), class),
+                Rule::Contains,
+            ),
+        ))(s)
+    }
+
+    fn class(s: &str) -> IResult<&str, CharClass> {
+        alt((
+            value(CharClass::Lower, tag("lower")),
+            value(CharClass::Upper, tag("upper")),
+            value(CharClass::Digit, tag("digit")),
+            value(CharClass::Symbol, tag("symbol")),
+        ))(s)
+    }
 }
 
 #[aoc_generator(day2)]
 fn generator(input: &str) -> Result<Vec<PasswordEntry>> {
     input
         .split('\n')
-        .map(|s| s.parse::<PasswordEntry>())
+        .enumerate()
+        .map(|(i, s)| {
+            s.parse::<PasswordEntry>()
+                .wrap_err_with(|| format!("line {}: {:?}", i + 1, s))
+        })
         .collect()
 }
 
@@ -187,6 +475,126 @@ mod test {
     }
     use super::nom_parser;
 
+    fn entry(s: &str) -> PasswordEntry {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn policy_explanations() {
+        assert_eq!(
+            CountPolicy.check(&entry("1-3 b: cdefg")),
+            Err(Violation::new(
+                "'b' appears 0 times, expected 1-3".to_string()
+            ))
+        );
+        assert_eq!(
+            PositionPolicy.check(&entry("2-9 c: ccccccccc")),
+            Err(Violation::new(
+                "'c' is at both positions 2 and 9".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn unicode() {
+        assert!(PositionPolicy.is_valid(&entry("1-2 é: éa")));
+        assert!(PositionPolicy.is_valid(&entry("1-2 é: aé")));
+        assert!(PositionPolicy.is_valid(&entry("1-3 é: aéé")));
+        assert!(PositionPolicy.is_valid(&entry("2-3 ü: füx")));
+        assert!(!PositionPolicy.is_valid(&entry("2-3 u: füx")));
+        assert!(CountPolicy.is_valid(&entry("2-2 ß: aßbß")));
+
+        let long = format!("{}b", "a".repeat(299));
+        assert!(PositionPolicy.is_valid(&entry(&format!("1-300 b: {}", long))));
+        assert!(CountPolicy.is_valid(&entry(&format!("299-1000 a: {}", long))));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |s: &str| s.parse::<PasswordEntry>().unwrap_err();
+        assert_eq!(
+            error("x-3 a: abc"),
+            ParseError {
+                offset: 0,
+                kind: ParseErrorKind::Range
+            }
+        );
+        assert_eq!(error("1-3 a abc").offset, 5);
+        assert_eq!(error("1-3 a abc").kind, ParseErrorKind::Letter);
+        assert_eq!(error("1-3 a:").kind, ParseErrorKind::Password);
+        assert_eq!(error("1-3 a:").offset, 6);
+        let overflow = error("1-99999999999999999999999 a: abc");
+        assert_eq!(overflow.offset, 2);
+        assert!(matches!(overflow.kind, ParseErrorKind::Bound(_)));
+        assert_eq!(
+            error("1-3 a abc").to_string(),
+            "expected a letter and ':' at 5"
+        );
+        assert!(generator("1-3 a: abc\n1-3 a abc").is_err());
+    }
+
+    #[test]
+    fn rule_parse() -> Result<()> {
+        assert_eq!(
+            "count and (min length 8 or no repeats) and contains digit".parse::<Rule>()?,
+            Rule::All(vec![
+                Rule::Count,
+                Rule::Any(vec![Rule::MinLength(8), Rule::NoRepeats]),
+                Rule::Contains(CharClass::Digit),
+            ])
+        );
+        assert!("count and".parse::<Rule>().is_err());
+        assert!("min length eight".parse::<Rule>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rule_check() -> Result<()> {
+        let rule: Rule = "count and (min length 8 or contains upper)".parse()?;
+        assert!(rule.is_valid(&entry("1-3 a: abcdefgh")));
+        assert!(rule.is_valid(&entry("1-3 a: aBc")));
+        assert_eq!(
+            rule.check(&entry("1-3 a: abc")).unwrap_err().reasons,
+            vec![
+                "password is 3 long, expected at least 8".to_string(),
+                "no Upper character".to_string()
+            ]
+        );
+        assert_eq!(
+            "no repeats"
+                .parse::<Rule>()?
+                .check(&entry("1-3 a: abbc"))
+                .unwrap_err()
+                .to_string(),
+            "'b' repeats at position 3"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn policy_file() -> Result<()> {
+        let config = "# the two puzzle policies
+sled = count
+toboggan = positions
+
+strict = positions and min length 12 # corporate wants more
+";
+        let policies = parse_policies(config)?;
+        assert_eq!(
+            policies,
+            vec![
+                ("sled".to_string(), Rule::Count),
+                ("toboggan".to_string(), Rule::Positions),
+                (
+                    "strict".to_string(),
+                    Rule::All(vec![Rule::Positions, Rule::MinLength(12)])
+                ),
+            ]
+        );
+        assert!(parse_policies("broken = count or").is_err());
+        Ok(())
+    }
+
     #[test]
     fn nom_parse() -> Result<()> {
         assert_eq!(
@@ -1,9 +1,9 @@
-use eyre::{Error, Result};
+use eyre::{eyre, Error, Result};
 use std::collections::HashSet;
-use std::{cmp::max, str::FromStr};
+use std::str::FromStr;
 
 #[derive(Debug)]
-struct Map {
+pub struct Map {
     width: usize,
     pub height: usize,
     trees: HashSet<[usize; 2]>,
@@ -13,8 +13,26 @@ impl FromStr for Map {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let trees: HashSet<_> = s
-            .split('\n')
+        let rows: Vec<_> = s.lines().collect();
+        let width = rows.first().map_or(0, |r| r.chars().count());
+        if width == 0 {
+            return Err(eyre!("The map has no columns"));
+        }
+        if let Some((row_idx, row)) = rows
+            .iter()
+            .enumerate()
+            .find(|(_, r)| r.chars().count() != width)
+        {
+            return Err(eyre!(
+                "Row {} is {} wide, expected {}",
+                row_idx,
+                row.chars().count(),
+                width
+            ));
+        }
+
+        let trees: HashSet<_> = rows
+            .iter()
             .enumerate()
             .flat_map(|(row_idx, row)| {
                 row.chars()
@@ -26,25 +44,64 @@ impl FromStr for Map {
             })
             .collect();
 
-        let (width, height) = trees.iter().fold((0, 0), |(max_x, max_y), [x, y]| {
-            (max(*x, max_x), max(*y, max_y))
-        });
-
         Ok(Map {
             width,
-            height,
+            height: rows.len(),
             trees,
         })
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slope {
+    pub fall: usize,
+    pub run: usize,
+    pub trees: u128,
+}
+
 impl Map {
     pub fn in_path(&self, path: impl Iterator<Item = [usize; 2]>) -> u128 {
         let path_set = path
-            .map(|[x, y]| [x % (self.width + 1), y])
+            .map(|[x, y]| [x % self.width, y])
             .collect::<HashSet<_>>();
         self.trees.intersection(&path_set).count() as u128
     }
+
+    /// Every slope with `1..=max_fall` and `0..=max_run`, fewest trees first.
+    pub fn rank_slopes(&self, max_fall: usize, max_run: usize) -> Vec<Slope> {
+        let mut slopes: Vec<_> = (1..=max_fall)
+            .flat_map(|fall| (0..=max_run).map(move |run| (fall, run)))
+            .map(|(fall, run)| Slope {
+                fall,
+                run,
+                trees: self.in_path(Toboggan::path(fall, run, self.height)),
+            })
+            .collect();
+        slopes.sort_by_key(|s| (s.trees, s.fall, s.run));
+        slopes
+    }
+
+    /// The map with the path drawn on it, `X` where it hits a tree and `O` where it doesn't.
+    pub fn render(&self, fall: usize, run: usize) -> String {
+        let path: HashSet<_> = Toboggan::path(fall, run, self.height)
+            .map(|[x, y]| [x % self.width, y])
+            .collect();
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(
+                        |x| match (path.contains(&[x, y]), self.trees.contains(&[x, y])) {
+                            (true, true) => 'X',
+                            (true, false) => 'O',
+                            (false, true) => '#',
+                            (false, false) => '.',
+                        },
+                    )
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 struct Toboggan {
@@ -72,7 +129,7 @@ impl Iterator for Toboggan {
         let y = self.fall * self.count;
         self.count += 1;
 
-        if y > self.height {
+        if y >= self.height {
             None
         } else {
             Some([x, y])
@@ -101,9 +158,7 @@ fn solve_part2(map: &Map) -> u128 {
 #[cfg(test)]
 mod test {
     use super::*;
-    #[test]
-    fn sample_1() -> Result<()> {
-        let map = "..##.......
+    const SAMPLE: &str = "..##.......
 #...#...#..
 .#....#..#.
 ..#.#...#.#
@@ -113,12 +168,60 @@ mod test {
 .#........#
 #.##...#...
 #...##....#
-.#..#...#.#"
-            .parse::<Map>()?;
+.#..#...#.#";
 
-        println!("{:#?}", map);
+    #[test]
+    fn sample_1() -> Result<()> {
+        let map = SAMPLE.parse::<Map>()?;
         assert_eq!(map.in_path(Toboggan::path(1, 3, map.height)), 7);
 
         Ok(())
     }
+
+    #[test]
+    fn sample_2() -> Result<()> {
+        let map = SAMPLE.parse::<Map>()?;
+        assert_eq!(solve_part2(&map), 336);
+        Ok(())
+    }
+
+    #[test]
+    fn width_from_rows() -> Result<()> {
+        // No tree in the last column, the old width came out one short.
+        let map = "#...\n.#..\n..#.\n#...".parse::<Map>()?;
+        assert_eq!(map.width, 4);
+        assert_eq!(map.height, 4);
+        assert_eq!(map.in_path(Toboggan::path(1, 1, map.height)), 3);
+        assert!("#..\n.#".parse::<Map>().is_err());
+        assert!(generator("\n").is_err());
+        assert!(generator("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ranking() -> Result<()> {
+        let map = SAMPLE.parse::<Map>()?;
+        let ranked = map.rank_slopes(2, 7);
+        assert_eq!(ranked.len(), 16);
+        assert!(ranked.windows(2).all(|w| w[0].trees <= w[1].trees));
+        assert!(ranked.contains(&Slope {
+            fall: 1,
+            run: 3,
+            trees: 7
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let map = SAMPLE.parse::<Map>()?;
+        let rendered = map.render(1, 3);
+        let rows: Vec<_> = rendered.lines().collect();
+        assert_eq!(rows.len(), 11);
+        assert_eq!(rows[0], "O.##.......");
+        assert_eq!(rows[1], "#..O#...#..");
+        assert_eq!(rows[4], ".X...##..#.");
+        assert_eq!(rendered.matches('X').count(), 7);
+        Ok(())
+    }
 }
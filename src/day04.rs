@@ -1,100 +1,593 @@
-use eyre::Result;
+use eyre::{eyre, Result};
+use nom::{combinator::all_consuming, IResult};
+use schema::Schema;
+use std::{collections::BTreeMap, fmt};
+
+// byr (Birth Year) - four digits; at least 1920 and at most 2002.
+// iyr (Issue Year) - four digits; at least 2010 and at most 2020.
+// eyr (Expiration Year) - four digits; at least 2020 and at most 2030.
+// hgt (Height) - a number followed by either cm or in:
+//     If cm, the number must be at least 150 and at most 193.
+//     If in, the number must be at least 59 and at most 76.
+// hcl (Hair Color) - a # followed by exactly six characters 0-9 or a-f.
+// ecl (Eye Color) - exactly one of: amb blu brn gry grn hzl oth.
+// pid (Passport ID) - a nine-digit number, including leading zeroes.
+// cid (Country ID) - ignored, missing or not.
+const PART1_SCHEMA: &str = r#"
+[byr]
+required = true
+[iyr]
+required = true
+[eyr]
+required = true
+[hgt]
+required = true
+[hcl]
+required = true
+[ecl]
+required = true
+[pid]
+required = true
+[cid]
+required = false
+"#;
+
+const PART2_SCHEMA: &str = r##"
+[byr]
+required = true
+range = [1920, 2002]
+
+[iyr]
+required = true
+range = [2010, 2020]
+
+[eyr]
+required = true
+range = [2020, 2030]
+
+[hgt]
+required = true
+units = { cm = [150, 193], in = [59, 76] }
+
+[hcl]
+required = true
+prefix = "#"
+charset = "0123456789abcdef"
+length = 6
+
+[ecl]
+required = true
+one_of = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"]
+
+[pid]
+required = true
+charset = "0123456789"
+length = 9
+
+[cid]
+required = false
+"##;
+
+/// The raw `key:value` pairs of one passport.
+pub type Fields = BTreeMap<String, String>;
 
 #[aoc_generator(day4)]
-fn generator(input: &str) -> Result<Vec<Passport>> {
-    parser::passports(input)
+fn generator(input: &str) -> Result<Vec<Fields>> {
+    parser::fields(input)
 }
 
 #[aoc(day4, part1)]
-fn solve_part1(passports: &[Passport]) -> usize {
-    passports.iter().filter(|p| p.is_valid()).count()
+fn solve_part1(passports: &[Fields]) -> Result<usize> {
+    let schema: Schema = PART1_SCHEMA.parse()?;
+    Ok(passports
+        .iter()
+        .filter(|p| schema.validate(p).is_valid())
+        .count())
 }
 
 #[aoc(day4, part2)]
-fn solve_part2(passports: &[Passport]) -> usize {
-    passports.iter().filter(|p| p.is_valid_2()).count()
+fn solve_part2(passports: &[Fields]) -> Result<usize> {
+    let schema: Schema = PART2_SCHEMA.parse()?;
+    Ok(passports
+        .iter()
+        .filter(|p| schema.validate(p).is_valid())
+        .count())
 }
 
-#[derive(Debug, Default)]
-pub struct Passport {
-    expiration: Option<u32>,
-    birth: Option<u32>,
-    issue: Option<u32>,
-    height: Option<String>,
-    hair_color: Option<String>,
-    eye_color: Option<String>,
-    pid: Option<String>,
-    cid: Option<String>,
-}
+pub mod schema {
+    use super::Fields;
+    use eyre::{eyre, Error, Result, WrapErr};
+    use nom::{
+        branch::alt,
+        bytes::complete::{is_not, tag},
+        character::complete::{alphanumeric1, char, digit1, space0},
+        combinator::{all_consuming, map, map_res, opt, recognize, value},
+        multi::{many1, separated_list0},
+        sequence::{delimited, pair, preceded, separated_pair, terminated},
+        IResult,
+    };
+    use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
 
-impl Passport {
-    pub fn is_valid(&self) -> bool {
-        self.eye_color.is_some()
-            && self.birth.is_some()
-            && self.issue.is_some()
-            && self.height.is_some()
-            && self.hair_color.is_some()
-            && self.pid.is_some()
-            && self.expiration.is_some()
-    }
-
-    // byr (Birth Year) - four digits; at least 1920 and at most 2002.
-    // iyr (Issue Year) - four digits; at least 2010 and at most 2020.
-    // eyr (Expiration Year) - four digits; at least 2020 and at most 2030.
-    // hgt (Height) - a number followed by either cm or in:
-    //     If cm, the number must be at least 150 and at most 193.
-    //     If in, the number must be at least 59 and at most 76.
-    // hcl (Hair Color) - a # followed by exactly six characters 0-9 or a-f.
-    // ecl (Eye Color) - exactly one of: amb blu brn gry grn hzl oth.
-    // pid (Passport ID) - a nine-digit number, including leading zeroes.
-    // cid (Country ID) - ignored, missing or not.
-    //
-    // Thinking about this, it feels like the parser should do the validation, but then we would
-    // also want to not return any invalid passports, and we would have to skip them...and that
-    // just seems like a lot of work...so instead this hack will have to do.
-    pub fn is_valid_2(&self) -> bool {
-        matches!(self.birth, Some(birth) if (1920..=2002).contains(&birth))
-            && matches!(self.issue, Some(issue) if (2010..=2020).contains(&issue))
-            && matches!(self.expiration, Some(expr) if (2020..=2030).contains(&expr))
-            && match self.height {
-                Some(ref height) => {
-                    //let height = height.bytes();
-                    let (val, unit) = height.split_at(height.len() - 2);
-
-                    if unit == "in" {
-                        val.parse::<u32>()
-                            .map_or(false, |val| val >= 59 && val <= 76)
-                    } else if unit == "cm" {
-                        val.parse::<u32>()
-                            .map_or(false, |val| val >= 150 && val <= 193)
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Rule {
+        /// The whole value is an integer in `min..=max`.
+        Range(u64, u64),
+        /// An integer directly followed by a unit, each unit with its own range.
+        Units(BTreeMap<String, (u64, u64)>),
+        /// Starts with `prefix`, the rest only uses `chars`.
+        Charset {
+            prefix: String,
+            chars: String,
+        },
+        /// Length of the value after any `prefix`.
+        Length(usize),
+        OneOf(Vec<String>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Failure {
+        Missing,
+        NotANumber(String),
+        OutOfRange { value: u64, min: u64, max: u64 },
+        UnknownUnit(String),
+        MissingPrefix(String),
+        BadChar(char),
+        Length { expected: usize, found: usize },
+        NotOneOf(String),
+    }
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Failure::Missing => write!(f, "missing"),
+                Failure::NotANumber(v) => write!(f, "{:?} is not a number", v),
+                Failure::OutOfRange { value, min, max } => {
+                    write!(f, "{} is not in {}..={}", value, min, max)
+                }
+                Failure::UnknownUnit(u) => write!(f, "unknown unit {:?}", u),
+                Failure::MissingPrefix(p) => write!(f, "does not start with {:?}", p),
+                Failure::BadChar(c) => write!(f, "{:?} is not allowed", c),
+                Failure::Length { expected, found } => {
+                    write!(f, "{} long, expected {}", found, expected)
+                }
+                Failure::NotOneOf(v) => write!(f, "{:?} is not an allowed value", v),
+            }
+        }
+    }
+
+    fn in_range(value: &str, min: u64, max: u64) -> Result<(), Failure> {
+        let n: u64 = value
+            .parse()
+            .map_err(|_| Failure::NotANumber(value.to_string()))?;
+        if (min..=max).contains(&n) {
+            Ok(())
+        } else {
+            Err(Failure::OutOfRange { value: n, min, max })
+        }
+    }
+
+    impl Rule {
+        fn check(&self, value: &str, prefix: &str) -> Result<(), Failure> {
+            match self {
+                Rule::Range(min, max) => in_range(value, *min, *max),
+                Rule::Units(units) => {
+                    let split = value
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(value.len());
+                    let (number, unit) = value.split_at(split);
+                    let (min, max) = units
+                        .get(unit)
+                        .ok_or_else(|| Failure::UnknownUnit(unit.to_string()))?;
+                    in_range(number, *min, *max)
+                }
+                Rule::Charset { prefix, chars } => {
+                    let rest = value
+                        .strip_prefix(prefix.as_str())
+                        .ok_or_else(|| Failure::MissingPrefix(prefix.clone()))?;
+                    match rest.chars().find(|c| !chars.contains(*c)) {
+                        Some(c) => Err(Failure::BadChar(c)),
+                        None => Ok(()),
+                    }
+                }
+                Rule::Length(expected) => {
+                    let found = value.strip_prefix(prefix).unwrap_or(value).chars().count();
+                    if found == *expected {
+                        Ok(())
+                    } else {
+                        Err(Failure::Length {
+                            expected: *expected,
+                            found,
+                        })
+                    }
+                }
+                Rule::OneOf(allowed) => {
+                    if allowed.iter().any(|a| a == value) {
+                        Ok(())
                     } else {
-                        false
+                        Err(Failure::NotOneOf(value.to_string()))
                     }
                 }
-                _ => false,
             }
-            && match self.hair_color {
-                Some(ref color) => {
-                    color.as_bytes()[0] == b"#"[0]
-                        && color.bytes().skip(1).all(|c| c.is_ascii_hexdigit())
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct FieldSchema {
+        pub required: bool,
+        pub rules: Vec<Rule>,
+    }
+
+    impl FieldSchema {
+        fn prefix(&self) -> &str {
+            self.rules
+                .iter()
+                .find_map(|r| match r {
+                    Rule::Charset { prefix, .. } => Some(prefix.as_str()),
+                    _ => None,
+                })
+                .unwrap_or("")
+        }
+
+        fn check(&self, value: Option<&String>) -> Vec<Failure> {
+            match value {
+                None if self.required => vec![Failure::Missing],
+                None => vec![],
+                Some(value) => self
+                    .rules
+                    .iter()
+                    .filter_map(|r| r.check(value, self.prefix()).err())
+                    .collect(),
+            }
+        }
+    }
+
+    /// Every rule that failed, by field. Fields that passed are left out.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Report {
+        pub failures: BTreeMap<String, Vec<Failure>>,
+    }
+
+    impl Report {
+        pub fn is_valid(&self) -> bool {
+            self.failures.is_empty()
+        }
+    }
+
+    impl fmt::Display for Report {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for (field, failures) in self.failures.iter() {
+                let failures: Vec<_> = failures.iter().map(|f| f.to_string()).collect();
+                writeln!(f, "{}: {}", field, failures.join(", "))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Per-field rules, read from a small subset of TOML:
+    ///
+    /// - one `[field]` header or one `key = value` per line, with `#` comments;
+    /// - bare keys only (letters, digits, `_`, `-`), no dotted or quoted keys;
+    /// - values are `true`/`false`, unsigned integers, `"strings"` without escapes,
+    ///   and `[arrays]` or `{ inline = tables }` of those, each kept on a single line.
+    ///
+    /// Anything else, including valid TOML such as multi-line arrays, is rejected with the
+    /// offending line.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Schema {
+        pub fields: BTreeMap<String, FieldSchema>,
+    }
+
+    impl Schema {
+        pub fn load(path: impl AsRef<Path>) -> Result<Schema> {
+            let path = path.as_ref();
+            std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Reading {}", path.display()))?
+                .parse()
+        }
+
+        // Fields the schema doesn't know about are ignored, the same way `cid` always was.
+        pub fn validate(&self, passport: &Fields) -> Report {
+            Report {
+                failures: self
+                    .fields
+                    .iter()
+                    .map(|(name, field)| (name.clone(), field.check(passport.get(name))))
+                    .filter(|(_, failures)| !failures.is_empty())
+                    .collect(),
+            }
+        }
+    }
+
+    // The small slice of TOML a schema needs.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Value {
+        Bool(bool),
+        Int(u64),
+        Str(String),
+        Array(Vec<Value>),
+        Table(Vec<(String, Value)>),
+    }
+
+    fn key(s: &str) -> IResult<&str, &str> {
+        recognize(many1(alt((alphanumeric1, tag("_"), tag("-")))))(s)
+    }
+
+    fn ws<'a, O>(
+        inner: impl FnMut(&'a str) -> IResult<&'a str, O>,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+        delimited(space0, inner, space0)
+    }
+
+    fn toml_value(s: &str) -> IResult<&str, Value> {
+        alt((
+            value(Value::Bool(true), tag("true")),
+            value(Value::Bool(false), tag("false")),
+            map(map_res(digit1, |d: &str| d.parse()), Value::Int),
+            map(
+                delimited(char('"'), opt(is_not("\"")), char('"')),
+                |s: Option<&str>| Value::Str(s.unwrap_or("").to_string()),
+            ),
+            map(
+                delimited(
+                    char('['),
+                    separated_list0(char(','), ws(toml_value)),
+                    char(']'),
+                ),
+                Value::Array,
+            ),
+            map(
+                delimited(
+                    char('{'),
+                    separated_list0(
+                        char(','),
+                        ws(map(
+                            separated_pair(key, ws(char('=')), toml_value),
+                            |(k, v)| (k.to_string(), v),
+                        )),
+                    ),
+                    char('}'),
+                ),
+                Value::Table,
+            ),
+        ))(s)
+    }
+
+    fn assignment(s: &str) -> IResult<&str, (&str, Value)> {
+        all_consuming(terminated(
+            separated_pair(key, ws(char('=')), toml_value),
+            pair(space0, opt(preceded(char('#'), opt(is_not("\n"))))),
+        ))(s)
+    }
+
+    fn bounds(v: &Value) -> Result<(u64, u64)> {
+        match v {
+            Value::Array(b) => match b.as_slice() {
+                [Value::Int(min), Value::Int(max)] => Ok((*min, *max)),
+                _ => Err(eyre!("Expected [min, max], found {:?}", b)),
+            },
+            v => Err(eyre!("Expected [min, max], found {:?}", v)),
+        }
+    }
+
+    fn string(v: &Value) -> Result<String> {
+        match v {
+            Value::Str(s) => Ok(s.clone()),
+            v => Err(eyre!("Expected a string, found {:?}", v)),
+        }
+    }
+
+    fn apply(field: &mut FieldSchema, key: &str, v: &Value) -> Result<()> {
+        match (key, v) {
+            ("required", Value::Bool(b)) => field.required = *b,
+            ("range", v) => {
+                let (min, max) = bounds(v)?;
+                field.rules.push(Rule::Range(min, max));
+            }
+            ("units", Value::Table(units)) => field.rules.push(Rule::Units(
+                units
+                    .iter()
+                    .map(|(unit, v)| Ok((unit.clone(), bounds(v)?)))
+                    .collect::<Result<_>>()?,
+            )),
+            ("length", Value::Int(n)) => field.rules.push(Rule::Length(*n as usize)),
+            ("one_of", Value::Array(values)) => field.rules.push(Rule::OneOf(
+                values.iter().map(string).collect::<Result<_>>()?,
+            )),
+            // `prefix` and `charset` end up in the same rule, whichever comes first.
+            ("prefix", v) | ("charset", v) => {
+                let v = string(v)?;
+                let existing = field.rules.iter_mut().find_map(|r| match r {
+                    Rule::Charset { prefix, chars } => Some((prefix, chars)),
+                    _ => None,
+                });
+                let (prefix, chars) = match existing {
+                    Some(existing) => existing,
+                    None => {
+                        field.rules.push(Rule::Charset {
+                            prefix: String::new(),
+                            chars: String::new(),
+                        });
+                        match field.rules.last_mut() {
+                            Some(Rule::Charset { prefix, chars }) => (prefix, chars),
+                            _ => unreachable!(),
+                        }
+                    }
+                };
+                if key == "prefix" {
+                    *prefix = v;
+                } else {
+                    *chars = v;
+                }
+            }
+            (key, v) => return Err(eyre!("Unexpected {} = {:?}", key, v)),
+        };
+        Ok(())
+    }
+
+    impl FromStr for Schema {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let mut schema = Schema::default();
+            let mut current: Option<String> = None;
+            for (line_no, line) in s.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Ok((_, name)) = all_consuming(delimited(char('['), ws(key), char(']')))(line)
+                {
+                    schema.fields.entry(name.to_string()).or_default();
+                    current = Some(name.to_string());
+                    continue;
                 }
-                _ => false,
+                let (_, (key, v)) = assignment(line).map_err(|_| {
+                    eyre!(
+                        "line {}: {:?} is outside the TOML subset a schema accepts",
+                        line_no + 1,
+                        line
+                    )
+                })?;
+                let field = current
+                    .as_ref()
+                    .and_then(|c| schema.fields.get_mut(c))
+                    .ok_or_else(|| eyre!("line {}: {} outside a [field]", line_no + 1, key))?;
+                apply(field, key, &v).wrap_err_with(|| format!("line {}", line_no + 1))?;
             }
-            && matches!(self.eye_color,
-                Some(ref color) if ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].contains(&color.as_str()))
-            && matches!(self.pid, Some(ref pid) if pid.len() == 9 && pid.bytes().all(|c| c.is_ascii_digit()))
+            Ok(schema)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Height {
+    Cm(u32),
+    In(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RgbColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EyeColor {
+    Amb,
+    Blu,
+    Brn,
+    Gry,
+    Grn,
+    Hzl,
+    Oth,
+}
+
+/// Nine digits, kept as text so the leading zeroes survive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassportId(String);
+
+/// A passport parsed by the fixed puzzle rules at the top of this file, the same ones
+/// `PART2_SCHEMA` spells out. It never reads a `Schema`: optional fields or extra eye
+/// colours can't be represented here, so use `Schema::validate` when the rules are loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passport {
+    pub birth: u32,
+    pub issue: u32,
+    pub expiration: u32,
+    pub height: Height,
+    pub hair_color: RgbColor,
+    pub eye_color: EyeColor,
+    pub pid: PassportId,
+    pub cid: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldError {
+    Missing(&'static str),
+    Invalid { field: &'static str, value: String },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "{} is missing", field),
+            FieldError::Invalid { field, value } => write!(f, "{} is invalid: {:?}", field, value),
+        }
+    }
+}
+
+fn typed_field<'a, T>(
+    fields: &'a Fields,
+    errors: &mut Vec<FieldError>,
+    name: &'static str,
+    parse: impl FnMut(&'a str) -> IResult<&'a str, T>,
+) -> Option<T> {
+    let value = match fields.get(name) {
+        Some(value) => value,
+        None => {
+            errors.push(FieldError::Missing(name));
+            return None;
+        }
+    };
+    match all_consuming(parse)(value) {
+        Ok((_, parsed)) => Some(parsed),
+        Err(_) => {
+            errors.push(FieldError::Invalid {
+                field: name,
+                value: value.clone(),
+            });
+            None
+        }
+    }
+}
+
+impl Passport {
+    /// Every field is checked, so the errors cover the whole passport and not just the first
+    /// bad field.
+    pub fn from_fields(fields: &Fields) -> Result<Passport, Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let birth = typed_field(fields, &mut errors, "byr", |s| parser::year(s, 1920, 2002));
+        let issue = typed_field(fields, &mut errors, "iyr", |s| parser::year(s, 2010, 2020));
+        let expiration = typed_field(fields, &mut errors, "eyr", |s| parser::year(s, 2020, 2030));
+        let height = typed_field(fields, &mut errors, "hgt", parser::height);
+        let hair_color = typed_field(fields, &mut errors, "hcl", parser::rgb_color);
+        let eye_color = typed_field(fields, &mut errors, "ecl", parser::eye_color);
+        let pid = typed_field(fields, &mut errors, "pid", parser::passport_id);
+
+        match (birth, issue, expiration, height, hair_color, eye_color, pid) {
+            (
+                Some(birth),
+                Some(issue),
+                Some(expiration),
+                Some(height),
+                Some(hair_color),
+                Some(eye_color),
+                Some(pid),
+            ) if errors.is_empty() => Ok(Passport {
+                birth,
+                issue,
+                expiration,
+                height,
+                hair_color,
+                eye_color,
+                pid,
+                cid: fields.get("cid").cloned(),
+            }),
+            _ => Err(errors),
+        }
     }
 }
 
-mod parser {
+pub mod parser {
     use nom::{
         branch::alt,
-        bytes::complete::tag,
+        bytes::complete::{tag, take_while_m_n},
         character::complete::char,
-        character::complete::none_of,
-        combinator::recognize,
+        character::complete::{digit1, none_of},
+        combinator::{eof, map, map_res, recognize, value, verify},
         multi::{many0, many1},
-        sequence::{pair, terminated},
+        sequence::{pair, preceded, terminated, tuple},
         IResult,
     };
 
@@ -115,42 +608,97 @@ mod parser {
                 ))),
                 char(':'),
             ),
-            terminated(recognize(many1(none_of("\n "))), alt((tag("\n"), tag(" ")))),
+            terminated(
+                recognize(many1(none_of("\n "))),
+                alt((tag("\n"), tag(" "), eof)),
+            ),
         )(input)
     }
 
-    fn passport(input: &str) -> IResult<&str, Passport> {
+    fn passport(input: &str) -> IResult<&str, Fields> {
         let (remain, fields) = terminated(many1(field), many0(tag("\n")))(input)?;
-        let mut passport = Passport::default();
-
-        for field in fields.iter() {
-            match field {
-                ("byr", val) => passport.birth = Some(val.parse().expect("Should be a number")),
-                ("iyr", val) => passport.issue = Some(val.parse().expect("Should be a number")),
-                ("eyr", val) => {
-                    passport.expiration = Some(val.parse().expect("Should be a number"))
-                }
-                ("hgt", val) => passport.height = Some(val.to_string()),
-                ("hcl", val) => passport.hair_color = Some(val.to_string()),
-                ("ecl", val) => passport.eye_color = Some(val.to_string()),
-                ("pid", val) => passport.pid = Some(val.to_string()),
-                ("cid", val) => passport.cid = Some(val.to_string()),
-                e => unreachable!("{:?}", e),
-            };
-        }
 
-        Ok((remain, passport))
+        Ok((
+            remain,
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        ))
     }
 
-    pub fn passports(input: &str) -> Result<Vec<Passport>> {
-        let (_, passports) = many1(passport)(input).expect("Should be fine!");
+    pub fn fields(input: &str) -> Result<Vec<Fields>> {
+        let (_, passports) = terminated(many1(passport), eof)(input)
+            .map_err(|e| eyre!("Passports did not parse: {}", e))?;
 
         Ok(passports)
     }
+
+    /// Typed passports, checked by the puzzle rules rather than a `Schema`.
+    pub fn passports(input: &str) -> Result<Vec<Result<Passport, Vec<FieldError>>>> {
+        Ok(fields(input)?.iter().map(Passport::from_fields).collect())
+    }
+
+    fn number(s: &str) -> IResult<&str, u32> {
+        map_res(digit1, |d: &str| d.parse())(s)
+    }
+
+    pub(super) fn year(s: &str, min: u32, max: u32) -> IResult<&str, u32> {
+        verify(number, |y| (min..=max).contains(y))(s)
+    }
+
+    pub(super) fn height(s: &str) -> IResult<&str, Height> {
+        alt((
+            map(
+                verify(terminated(number, tag("cm")), |h| (150..=193).contains(h)),
+                Height::Cm,
+            ),
+            map(
+                verify(terminated(number, tag("in")), |h| (59..=76).contains(h)),
+                Height::In,
+            ),
+        ))(s)
+    }
+
+    fn hex_byte(s: &str) -> IResult<&str, u8> {
+        map_res(
+            take_while_m_n(2, 2, |c: char| {
+                c.is_ascii_digit() || ('a'..='f').contains(&c)
+            }),
+            |h| u8::from_str_radix(h, 16),
+        )(s)
+    }
+
+    pub(super) fn rgb_color(s: &str) -> IResult<&str, RgbColor> {
+        map(
+            preceded(char('#'), tuple((hex_byte, hex_byte, hex_byte))),
+            |(r, g, b)| RgbColor { r, g, b },
+        )(s)
+    }
+
+    pub(super) fn eye_color(s: &str) -> IResult<&str, EyeColor> {
+        alt((
+            value(EyeColor::Amb, tag("amb")),
+            value(EyeColor::Blu, tag("blu")),
+            value(EyeColor::Brn, tag("brn")),
+            value(EyeColor::Gry, tag("gry")),
+            value(EyeColor::Grn, tag("grn")),
+            value(EyeColor::Hzl, tag("hzl")),
+            value(EyeColor::Oth, tag("oth")),
+        ))(s)
+    }
+
+    pub(super) fn passport_id(s: &str) -> IResult<&str, PassportId> {
+        map(
+            take_while_m_n(9, 9, |c: char| c.is_ascii_digit()),
+            |id: &str| PassportId(id.to_string()),
+        )(s)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::schema::{Failure, FieldSchema, Rule};
     use super::*;
 
     #[test]
@@ -169,14 +717,200 @@ hgt:179cm
 hcl:#cfa07d eyr:2025 pid:166559648
 iyr:2011 ecl:brn hgt:59in";
 
+        assert_eq!(solve_part1(&parser::fields(input)?)?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sample2() -> Result<()> {
+        let invalid = "eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+iyr:2019
+hcl:#602927 eyr:1967 hgt:170cm
+ecl:grn pid:012533040 byr:1946
+
+hcl:dab227 iyr:2012
+ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
+
+hgt:59cm ecl:zzz
+eyr:2038 hcl:74454a iyr:2023
+pid:3556412378 byr:2007
+";
+        let valid = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:2029 ecl:blu cid:129 byr:1989
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+hcl:#888785
+hgt:164cm byr:2001 iyr:2015 cid:88
+pid:545766238 ecl:hzl
+eyr:2022
+
+iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719
+";
+        assert_eq!(solve_part2(&parser::fields(invalid)?)?, 0);
+        assert_eq!(solve_part2(&parser::fields(valid)?)?, 4);
+
+        assert!(parser::passports(invalid)?.iter().all(Result::is_err));
+        assert!(parser::passports(valid)?.iter().all(Result::is_ok));
+        Ok(())
+    }
+
+    #[test]
+    fn typed_passport() -> Result<()> {
+        let passports = parser::passports(
+            "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980\nhcl:#623a2f",
+        )?;
         assert_eq!(
-            parser::passports(input)?
-                .iter()
-                .filter(|p| p.is_valid())
-                .count(),
-            2
+            passports[0],
+            Ok(Passport {
+                birth: 1980,
+                issue: 2012,
+                expiration: 2030,
+                height: Height::In(74),
+                hair_color: RgbColor {
+                    r: 0x62,
+                    g: 0x3a,
+                    b: 0x2f
+                },
+                eye_color: EyeColor::Grn,
+                pid: PassportId("087499704".to_string()),
+                cid: None,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn typed_agrees_with_part2_schema() -> Result<()> {
+        let input = include_str!("../input/2020/day4.txt");
+        let schema: Schema = PART2_SCHEMA.parse()?;
+        let fields = parser::fields(input)?;
+        for (fields, typed) in fields.iter().zip(parser::passports(input)?) {
+            assert_eq!(
+                schema.validate(fields).is_valid(),
+                typed.is_ok(),
+                "{:?}",
+                fields
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn field_errors() -> Result<()> {
+        // Values too short for the old `split_at` / `as_bytes()[0]` checks.
+        let passports = parser::passports("hgt:m hcl:# ecl:zzz pid:1 byr:1920 iyr:2010\n")?;
+        assert_eq!(
+            passports[0],
+            Err(vec![
+                FieldError::Missing("eyr"),
+                FieldError::Invalid {
+                    field: "hgt",
+                    value: "m".to_string()
+                },
+                FieldError::Invalid {
+                    field: "hcl",
+                    value: "#".to_string()
+                },
+                FieldError::Invalid {
+                    field: "ecl",
+                    value: "zzz".to_string()
+                },
+                FieldError::Invalid {
+                    field: "pid",
+                    value: "1".to_string()
+                },
+            ])
+        );
+        assert!(parser::passports("byr:1920 bad").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn report() -> Result<()> {
+        let schema: Schema = PART2_SCHEMA.parse()?;
+        let passport = parser::fields("hgt:59cm ecl:zzz\neyr:2038 hcl:74454a iyr:2023\n")?;
+        let report = schema.validate(&passport[0]);
+        assert_eq!(report.failures["byr"], vec![Failure::Missing]);
+        assert_eq!(
+            report.failures["hgt"],
+            vec![Failure::OutOfRange {
+                value: 59,
+                min: 150,
+                max: 193
+            }]
+        );
+        assert_eq!(
+            report.failures["hcl"],
+            vec![Failure::MissingPrefix("#".to_string())]
+        );
+        assert_eq!(
+            report.failures["ecl"],
+            vec![Failure::NotOneOf("zzz".to_string())]
         );
+        assert!(!report.failures.contains_key("cid"));
+        assert!(report
+            .to_string()
+            .contains("eyr: 2038 is not in 2020..=2030\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn schema_parse() -> Result<()> {
+        let schema: Schema = "# a comment
+[hcl]
+required = true # trailing comment
+charset = \"0123456789abcdef\"
+prefix = \"#\"
+length = 6
+
+[hgt]
+units = { cm = [150, 193], in = [59, 76] }
+"
+        .parse()?;
+        assert_eq!(
+            schema.fields["hcl"],
+            FieldSchema {
+                required: true,
+                rules: vec![
+                    Rule::Charset {
+                        prefix: "#".to_string(),
+                        chars: "0123456789abcdef".to_string()
+                    },
+                    Rule::Length(6)
+                ]
+            }
+        );
+        assert!(!schema.fields["hgt"].required);
+        assert!("required = true".parse::<Schema>().is_err());
+        assert!("[byr]\nrange = [1920]".parse::<Schema>().is_err());
+        assert!("[byr]\nmaximum = 3".parse::<Schema>().is_err());
+        Ok(())
+    }
 
+    #[test]
+    fn schema_subset() -> Result<()> {
+        // All valid TOML, but outside what the loader reads.
+        for (toml, line) in [
+            ("[byr]\nrange = [\n  1920,\n  2002,\n]", 2),
+            ("[hcl]\nprefix = \"\\\"\"", 2),
+            ("[hgt]\nunits.cm = [150, 193]", 2),
+            ("[hgt]\n\"units\" = { cm = [150, 193] }", 2),
+        ]
+        .iter()
+        {
+            let err = toml.parse::<Schema>().unwrap_err().to_string();
+            assert!(
+                err.starts_with(&format!("line {}: ", line))
+                    && err.ends_with("is outside the TOML subset a schema accepts"),
+                "{}",
+                err
+            );
+        }
         Ok(())
     }
 }
@@ -1,52 +1,297 @@
-use eyre::{Error, Result};
-use std::str::FromStr;
+use eyre::Result;
+use std::{collections::BTreeSet, fmt, str::FromStr};
 
 #[aoc_generator(day5)]
-fn generator(input: &str) -> Result<Vec<u64>> {
+fn generator(input: &str) -> Result<Vec<BoardingPass>> {
     input
         .split('\n')
-        .map(|pass| Ok(pass.parse::<SeatID>()?.0))
+        .map(|pass| Ok(pass.parse::<BoardingPass>()?))
         .collect()
 }
 
 #[aoc(day5, part1)]
-fn solve_part1(passes: &[u64]) -> u64 {
-    *passes.iter().max().unwrap()
+fn solve_part1(passes: &[BoardingPass]) -> u64 {
+    let geometry = Geometry::default();
+    passes.iter().map(|p| geometry.seat_id(p)).max().unwrap()
 }
 
 #[aoc(day5, part2)]
-fn solve_part2(passes: &[u64]) -> u64 {
-    let mut passes: Vec<_> = passes.to_vec();
-    passes.sort_unstable();
-    passes
-        .iter()
-        .skip(1)
-        .fold(passes.first().unwrap(), |last, cur| {
-            if last + 1 != *cur {
-                last
+fn solve_part2(passes: &[BoardingPass]) -> u64 {
+    let map = SeatMap::new(Geometry::default(), passes);
+    map.candidates()
+        .first()
+        .map(|pass| map.geometry.seat_id(pass))
+        .unwrap()
+}
+
+/// Every id between the lowest and highest seat taken that nobody has a pass for.
+pub fn missing_seats(ids: &[u64]) -> Vec<u64> {
+    let taken: BTreeSet<_> = ids.iter().cloned().collect();
+    match (taken.iter().next(), taken.iter().next_back()) {
+        (Some(first), Some(last)) => (*first..*last).filter(|id| !taken.contains(id)).collect(),
+        _ => vec![],
+    }
+}
+
+/// How many bits pick the row and the column, and which letters stand for 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub row_bits: u32,
+    pub column_bits: u32,
+    pub row_letters: (char, char),
+    pub column_letters: (char, char),
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry {
+            row_bits: 7,
+            column_bits: 3,
+            row_letters: ('F', 'B'),
+            column_letters: ('L', 'R'),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassError {
+    Length {
+        expected: usize,
+        found: usize,
+    },
+    Letter {
+        position: usize,
+        found: char,
+        expected: (char, char),
+    },
+    OutOfRange {
+        row: u32,
+        column: u32,
+    },
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::Length { expected, found } => {
+                write!(f, "pass is {} long, expected {}", found, expected)
+            }
+            PassError::Letter {
+                position,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{:?} at {} should be {:?} or {:?}",
+                found, position, expected.0, expected.1
+            ),
+            PassError::OutOfRange { row, column } => {
+                write!(f, "row {} column {} is not on the plane", row, column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+impl Geometry {
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> u32 {
+        1 << self.column_bits
+    }
+
+    pub fn decode(&self, s: &str) -> Result<BoardingPass, PassError> {
+        let expected = (self.row_bits + self.column_bits) as usize;
+        let found = s.chars().count();
+        if found != expected {
+            return Err(PassError::Length { expected, found });
+        }
+
+        let mut bits = s.chars().enumerate().map(|(position, c)| {
+            let letters = if position < self.row_bits as usize {
+                self.row_letters
             } else {
-                cur
+                self.column_letters
+            };
+            match c {
+                c if c == letters.0 => Ok(0),
+                c if c == letters.1 => Ok(1),
+                found => Err(PassError::Letter {
+                    position,
+                    found,
+                    expected: letters,
+                }),
             }
+        });
+        let mut read = |n| (0..n).try_fold(0, |acc, _| Ok((acc << 1) | bits.next().unwrap()?));
+        Ok(BoardingPass {
+            row: read(self.row_bits)?,
+            column: read(self.column_bits)?,
         })
-        .to_owned()
+    }
+
+    pub fn encode(&self, pass: &BoardingPass) -> Result<String, PassError> {
+        if pass.row >= self.rows() || pass.column >= self.columns() {
+            return Err(PassError::OutOfRange {
+                row: pass.row,
+                column: pass.column,
+            });
+        }
+        let letters = |value: u32, bits: u32, (zero, one): (char, char)| {
+            (0..bits)
+                .rev()
+                .map(move |bit| if value & (1 << bit) == 0 { zero } else { one })
+        };
+        Ok(letters(pass.row, self.row_bits, self.row_letters)
+            .chain(letters(pass.column, self.column_bits, self.column_letters))
+            .collect())
+    }
+
+    pub fn seat_id(&self, pass: &BoardingPass) -> u64 {
+        pass.row as u64 * self.columns() as u64 + pass.column as u64
+    }
+
+    pub fn from_seat_id(&self, id: u64) -> BoardingPass {
+        BoardingPass {
+            row: (id / self.columns() as u64) as u32,
+            column: (id % self.columns() as u64) as u32,
+        }
+    }
 }
 
-struct SeatID(u64);
-impl FromStr for SeatID {
-    type Err = Error;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BoardingPass {
+    row: u32,
+    column: u32,
+}
+
+impl BoardingPass {
+    pub fn new(row: u32, column: u32) -> Self {
+        BoardingPass { row, column }
+    }
+
+    pub fn row(&self) -> u32 {
+        self.row
+    }
 
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+impl FromStr for BoardingPass {
+    type Err = PassError;
+
+    /// Decodes with the puzzle's `Geometry::default()`; call `decode` on any other geometry.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(SeatID(
-            s.chars()
-                .map(|d| match d {
-                    'F' => 0,
-                    'L' => 0,
-                    'B' => 1,
-                    'R' => 1,
-                    _ => unreachable!(),
-                })
-                .fold(0, |acc, v| (acc << 1) | v),
-        ))
+        Geometry::default().decode(s)
+    }
+}
+
+/// Which seats of the plane have a pass, row by row.
+#[derive(Debug, Clone)]
+pub struct SeatMap {
+    pub geometry: Geometry,
+    occupied: Vec<Vec<bool>>,
+}
+
+impl SeatMap {
+    /// Passes that fall outside the geometry are ignored.
+    pub fn new(geometry: Geometry, passes: &[BoardingPass]) -> Self {
+        let mut occupied = vec![vec![false; geometry.columns() as usize]; geometry.rows() as usize];
+        for pass in passes {
+            if let Some(seat) = occupied
+                .get_mut(pass.row as usize)
+                .and_then(|row| row.get_mut(pass.column as usize))
+            {
+                *seat = true;
+            }
+        }
+        SeatMap { geometry, occupied }
+    }
+
+    pub fn is_occupied(&self, pass: &BoardingPass) -> bool {
+        self.occupied
+            .get(pass.row as usize)
+            .and_then(|row| row.get(pass.column as usize))
+            .cloned()
+            .unwrap_or(false)
+    }
+
+    pub fn occupied_count(&self) -> usize {
+        self.occupied.iter().flatten().filter(|seat| **seat).count()
+    }
+
+    fn row_is_empty(&self, row: usize) -> bool {
+        self.occupied[row].iter().all(|seat| !seat)
+    }
+
+    /// Rows at the front of the plane without a single pass.
+    pub fn missing_front_rows(&self) -> Vec<u32> {
+        (0..self.occupied.len())
+            .take_while(|row| self.row_is_empty(*row))
+            .map(|row| row as u32)
+            .collect()
+    }
+
+    /// Rows at the back of the plane without a single pass, front to back.
+    pub fn missing_back_rows(&self) -> Vec<u32> {
+        let front = self.missing_front_rows().len();
+        let mut rows: Vec<_> = (front..self.occupied.len())
+            .rev()
+            .take_while(|row| self.row_is_empty(*row))
+            .map(|row| row as u32)
+            .collect();
+        rows.reverse();
+        rows
+    }
+
+    /// Free seats whose ids either side are both taken.
+    pub fn candidates(&self) -> Vec<BoardingPass> {
+        let seats = self.geometry.rows() as u64 * self.geometry.columns() as u64;
+        let taken = |id: u64| self.is_occupied(&self.geometry.from_seat_id(id));
+        (1..seats.saturating_sub(1))
+            .filter(|id| !taken(*id) && taken(id - 1) && taken(id + 1))
+            .map(|id| self.geometry.from_seat_id(id))
+            .collect()
+    }
+
+    /// One line per row, `#` for taken, `.` for free and `?` for a candidate seat,
+    /// with an aisle down the middle.
+    pub fn render(&self) -> String {
+        let candidates: BTreeSet<_> = self.candidates().into_iter().collect();
+        let aisle = self.geometry.columns() as usize / 2;
+        let width = self.occupied.len().saturating_sub(1).to_string().len();
+        self.occupied
+            .iter()
+            .enumerate()
+            .map(|(row, seats)| {
+                let seats: String = seats
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(column, taken)| {
+                        let seat = if *taken {
+                            '#'
+                        } else if candidates.contains(&BoardingPass::new(row as u32, column as u32))
+                        {
+                            '?'
+                        } else {
+                            '.'
+                        };
+                        if column == aisle && column > 0 {
+                            vec![' ', seat]
+                        } else {
+                            vec![seat]
+                        }
+                    })
+                    .collect();
+                format!("{:>width$} {}", row, seats, width = width)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 }
 
@@ -56,8 +301,106 @@ mod test {
 
     #[test]
     fn sample_0() -> Result<()> {
-        assert_eq!("FBFBBFFRLR".parse::<SeatID>()?.0, 357);
+        let pass = "FBFBBFFRLR".parse::<BoardingPass>()?;
+        assert_eq!(Geometry::default().seat_id(&pass), 357);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode() -> Result<()> {
+        let geometry = Geometry::default();
+        let pass = "BFFFBBFRRR".parse::<BoardingPass>()?;
+        assert_eq!(
+            (pass.row(), pass.column(), geometry.seat_id(&pass)),
+            (70, 7, 567)
+        );
+        let pass = "BBFFBBFRLL".parse::<BoardingPass>()?;
+        assert_eq!(
+            (pass.row(), pass.column(), geometry.seat_id(&pass)),
+            (102, 4, 820)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encode() {
+        let geometry = Geometry::default();
+        for pass in &["FBFBBFFRLR", "BFFFBBFRRR", "FFFBBBFRRR", "BBFFBBFRLL"] {
+            let decoded = pass.parse::<BoardingPass>().unwrap();
+            assert_eq!(geometry.encode(&decoded).unwrap(), *pass);
+        }
+        assert_eq!(geometry.from_seat_id(357), BoardingPass::new(44, 5));
+        assert!(geometry.encode(&BoardingPass::new(128, 0)).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            "FBFBBFFRL".parse::<BoardingPass>(),
+            Err(PassError::Length {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            "FBFBBFFRXR".parse::<BoardingPass>(),
+            Err(PassError::Letter {
+                position: 8,
+                found: 'X',
+                expected: ('L', 'R')
+            })
+        );
+        // Row letters are not accepted in the column part.
+        assert!("FBFBBFFFLR".parse::<BoardingPass>().is_err());
+    }
+
+    #[test]
+    fn custom_geometry() -> Result<()> {
+        let geometry = Geometry {
+            row_bits: 2,
+            column_bits: 2,
+            row_letters: ('0', '1'),
+            column_letters: ('a', 'b'),
+        };
+        let pass = geometry.decode("10ba")?;
+        assert_eq!((pass.row(), pass.column()), (2, 2));
+        assert_eq!(geometry.seat_id(&pass), 10);
+        assert_eq!(geometry.encode(&pass)?, "10ba");
+        Ok(())
+    }
 
+    #[test]
+    fn gaps() {
+        assert_eq!(missing_seats(&[3, 4, 6, 9, 10]), vec![5, 7, 8]);
+        assert_eq!(missing_seats(&[]), Vec::<u64>::new());
+        let passes = generator("FFFFFFFLLL\nFFFFFFFLLR\nFFFFFFFLRR").unwrap();
+        assert_eq!(solve_part2(&passes), 2);
+    }
+
+    #[test]
+    fn seat_map() -> Result<()> {
+        let geometry = Geometry {
+            row_bits: 2,
+            column_bits: 2,
+            row_letters: ('F', 'B'),
+            column_letters: ('L', 'R'),
+        };
+        let passes = ["FBLL", "FBLR", "FBRR", "BFLL", "BFRL"]
+            .iter()
+            .map(|pass| geometry.decode(pass))
+            .collect::<Result<Vec<_>, _>>()?;
+        let map = SeatMap::new(geometry, &passes);
+        assert_eq!(map.occupied_count(), 5);
+        assert!(map.is_occupied(&BoardingPass::new(1, 1)));
+        assert!(!map.is_occupied(&BoardingPass::new(1, 2)));
+        assert_eq!(map.missing_front_rows(), vec![0]);
+        assert_eq!(map.missing_back_rows(), vec![3]);
+        assert_eq!(
+            map.candidates(),
+            vec![BoardingPass::new(1, 2), BoardingPass::new(2, 1)]
+        );
+        assert_eq!(map.render(), "0 .. ..\n1 ## ?#\n2 #? #.\n3 .. ..");
         Ok(())
     }
 }
@@ -1,40 +1,158 @@
+use eyre::{eyre, Result};
+use std::iter::FromIterator;
+
 #[aoc_generator(day6)]
-fn generate(input: &str) -> Vec<Vec<u32>> {
-    input
-        .split("\n\n")
-        .map(|group| {
-            group
-                .split('\n')
-                .map(|person| person.bytes().fold(0u32, |acc, b| acc | 1 << (b - b'a')))
-                .collect()
-        })
-        .collect()
+fn generate(input: &str) -> Result<Vec<Group>> {
+    let mut groups = vec![Group::default()];
+    for (line_idx, line) in input.lines().enumerate() {
+        if line.trim_end_matches('\r').is_empty() {
+            if !groups.last().unwrap().people.is_empty() {
+                groups.push(Group::default());
+            }
+            continue;
+        }
+        let person = QuestionSet::parse(line.trim_end_matches('\r')).map_err(|(column, c)| {
+            eyre!("{:?} at line {} column {}", c, line_idx + 1, column + 1)
+        })?;
+        groups.last_mut().unwrap().people.push(person);
+    }
+    if groups.last().unwrap().people.is_empty() {
+        groups.pop();
+    }
+    Ok(groups)
 }
 
 #[aoc(day6, part1)]
-fn solve_part1(questionaires: &[Vec<u32>]) -> u32 {
-    questionaires
-        .iter()
-        .map(|group| {
-            group
-                .iter()
-                .fold(0u32, |acc, person| acc | person)
-                .count_ones()
-        })
-        .sum()
+fn solve_part1(questionaires: &[Group]) -> usize {
+    questionaires.iter().map(|group| group.any().len()).sum()
 }
 
 #[aoc(day6, part2)]
-fn solve_part2(questionaires: &[Vec<u32>]) -> u32 {
-    questionaires
-        .iter()
-        .map(|group| {
-            group
-                .iter()
-                .fold(u32::MAX, |acc, person| acc & person)
-                .count_ones()
-        })
-        .sum()
+fn solve_part2(questionaires: &[Group]) -> usize {
+    questionaires.iter().map(|group| group.all().len()).sum()
+}
+
+/// Questions answered "yes", one bit per ASCII question id, growing as needed.
+#[derive(Debug, Clone, Default)]
+pub struct QuestionSet {
+    words: Vec<u64>,
+}
+
+impl QuestionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Any printable ASCII character is a question id; the first other one is
+    /// returned with its position.
+    pub fn parse(answers: &str) -> Result<Self, (usize, char)> {
+        answers
+            .chars()
+            .enumerate()
+            .map(|(column, c)| match c {
+                c if c.is_ascii_graphic() => Ok(c as u8),
+                c => Err((column, c)),
+            })
+            .collect()
+    }
+
+    pub fn insert(&mut self, question: u8) {
+        let (word, bit) = (question as usize / 64, question % 64);
+        if self.words.len() <= word {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << bit;
+    }
+
+    pub fn contains(&self, question: u8) -> bool {
+        self.words
+            .get(question as usize / 64)
+            .is_some_and(|word| word & (1 << (question % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.words.len() * 64)
+            .map(|q| q as u8)
+            .filter(move |q| self.contains(*q))
+    }
+
+    fn zip_with(&self, other: &Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        let len = self.words.len().max(other.words.len());
+        let word = |set: &Self, i: usize| set.words.get(i).cloned().unwrap_or(0);
+        QuestionSet {
+            words: (0..len).map(|i| f(word(self, i), word(other, i))).collect(),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a | b)
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & b)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.zip_with(other, |a, b| a & !b)
+    }
+}
+
+impl PartialEq for QuestionSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.zip_with(other, |a, b| a ^ b).is_empty()
+    }
+}
+
+impl Eq for QuestionSet {}
+
+impl FromIterator<u8> for QuestionSet {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let mut set = QuestionSet::new();
+        for question in iter {
+            set.insert(question);
+        }
+        set
+    }
+}
+
+/// Everyone travelling together, one answer set per person.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    pub people: Vec<QuestionSet>,
+}
+
+impl Group {
+    /// Questions anyone answered.
+    pub fn any(&self) -> QuestionSet {
+        self.people
+            .iter()
+            .fold(QuestionSet::new(), |acc, person| acc.union(person))
+    }
+
+    /// Questions everyone answered.
+    pub fn all(&self) -> QuestionSet {
+        let mut people = self.people.iter();
+        match people.next() {
+            Some(first) => people.fold(first.clone(), |acc, person| acc.intersection(person)),
+            None => QuestionSet::new(),
+        }
+    }
+
+    /// Questions at least `k` people answered.
+    pub fn at_least(&self, k: usize) -> QuestionSet {
+        self.any()
+            .iter()
+            .filter(|q| self.people.iter().filter(|p| p.contains(*q)).count() >= k)
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -57,18 +175,44 @@ a
 
 b";
     #[test]
-    fn sample_part1() {
-        let questionaires = generate(INPUT);
+    fn sample_part1() -> Result<()> {
+        let questionaires = generate(INPUT)?;
 
         println!("{:?}", questionaires);
 
-        assert_eq!(solve_part1(questionaires.as_slice()), 11)
+        assert_eq!(solve_part1(questionaires.as_slice()), 11);
+        Ok(())
     }
 
     #[test]
-    fn sample_part2() {
-        let questionaires = generate(INPUT);
+    fn sample_part2() -> Result<()> {
+        let questionaires = generate(INPUT)?;
 
-        assert_eq!(solve_part2(questionaires.as_slice()), 6)
+        assert_eq!(solve_part2(questionaires.as_slice()), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn crlf_and_wide_ids() -> Result<()> {
+        let groups = generate("aZ9\r\nZ~\r\n\r\n!\r\n")?;
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].any().iter().collect::<Vec<_>>(), b"9Za~");
+        assert_eq!(groups[0].all(), "Z".bytes().collect());
+        assert!(generate("ab\na c").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn set_algebra() -> Result<()> {
+        let group = &generate("abc\nbcd\ncde\nc~")?[0];
+        assert_eq!(group.at_least(1), group.any());
+        assert_eq!(group.at_least(2), "bcd".bytes().collect());
+        assert_eq!(group.at_least(4), group.all());
+        assert_eq!(group.at_least(5), QuestionSet::new());
+        let (a, b) = (&group.people[0], &group.people[3]);
+        assert_eq!(a.difference(b), "ab".bytes().collect());
+        assert_eq!(b.difference(a), "~".bytes().collect());
+        assert_eq!(a.union(b).len(), 4);
+        Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use eyre::{Error, Result};
+use eyre::{eyre, Result};
 use nom::{
     branch::alt,
     bytes::complete::tag,
@@ -10,34 +10,125 @@ use nom::{
     sequence::{pair, separated_pair, terminated, tuple},
     IResult,
 };
-use std::{collections::HashMap, collections::HashSet, collections::VecDeque, str::FromStr};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    ops::Range,
+    str::FromStr,
+};
 
 #[aoc_generator(day7)]
 fn generator(input: &str) -> Result<Vec<Rule>> {
-    input.split('\n').map(|rule| rule.parse::<Rule>()).collect()
+    Ok(parse_rules(input)?)
+}
+
+/// Every rule in `input`, or every line that failed to parse.
+pub fn parse_rules(input: &str) -> Result<Vec<Rule>, RuleErrors> {
+    let (rules, errors): (Vec<_>, Vec<_>) = input
+        .split('\n')
+        .enumerate()
+        .map(|(idx, line)| Rule::parse(idx + 1, line))
+        .partition(Result::is_ok);
+    if errors.is_empty() {
+        Ok(rules.into_iter().map(Result::unwrap).collect())
+    } else {
+        Err(RuleErrors(
+            errors.into_iter().map(Result::unwrap_err).collect(),
+        ))
+    }
 }
 
-struct Rule {
+#[derive(Debug)]
+pub struct Rule {
     color: String,
     contains: Vec<(u32, String)>,
 }
 
-impl FromStr for Rule {
-    type Err = Error;
+/// A rule that didn't parse, with the byte span of the offending text in its line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleError {
+    pub line: usize,
+    pub span: Range<usize>,
+    pub message: String,
+    pub text: String,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (_, (bag_color, bag_contains)) = pair(color, contains)(s).expect("Should be parsable");
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "line {}: {}", self.line, self.message)?;
+        writeln!(f, "    {}", self.text)?;
+        write!(
+            f,
+            "    {}{}",
+            " ".repeat(self.text[..self.span.start].chars().count()),
+            "^".repeat(self.text[self.span.clone()].chars().count().max(1))
+        )
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleErrors(pub Vec<RuleError>);
+
+impl fmt::Display for RuleErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bad rule(s)", self.0.len())?;
+        for error in self.0.iter() {
+            write!(f, "\n{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuleErrors {}
+
+impl Rule {
+    fn parse(line: usize, s: &str) -> Result<Self, RuleError> {
+        let error = |at: &str, message: String| {
+            let at = at.trim_start();
+            let start = s.len() - at.len();
+            let len = at.find(' ').unwrap_or(at.len());
+            RuleError {
+                line,
+                span: start..start + len,
+                message,
+                text: s.to_string(),
+            }
+        };
+        let (rest, (bag_color, bag_contains)) = pair(color, contains)(s).map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => error(e.input, "unexpected text".into()),
+            nom::Err::Incomplete(_) => error("", "rule ends early".into()),
+        })?;
+        if !rest.is_empty() {
+            return Err(error(rest, "trailing text after the rule".into()));
+        }
         Ok(Rule {
             color: bag_color.to_string(),
             contains: bag_contains
                 .iter()
                 .filter(|(count, _)| count != &"no")
-                .map(|(count, color)| Ok((count.parse::<u32>()?, color.to_string())))
-                .collect::<Result<Vec<_>>>()?,
+                .map(|(count, color)| {
+                    let at = &s[count.as_ptr() as usize - s.as_ptr() as usize..];
+                    match count.replace('_', "").parse::<u32>() {
+                        Ok(n) => Ok((n, color.to_string())),
+                        Err(e) => Err(error(at, format!("bad count: {}", e))),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?,
         })
     }
 }
 
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::parse(1, s)
+    }
+}
+
 fn color(input: &str) -> IResult<&str, &str> {
     terminated(
         take_until(" bag"),
@@ -59,82 +150,229 @@ fn contains(input: &str) -> IResult<&str, Vec<(&str, &str)>> {
     )(input)
 }
 
-#[aoc(day7, part1)]
-fn solve_part1(input: &[Rule]) -> u32 {
-    let mut holds_key: HashMap<&str, HashSet<&str>> = HashMap::new();
-    for rule in input.iter() {
-        for (_, bag) in rule.contains.iter() {
-            holds_key.entry(&bag).or_default().insert(&rule.color);
-        }
+/// Bag colours interned to ids, with "holds `count` of" edges both ways.
+#[derive(Debug, Default)]
+pub struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, BagId>,
+    contains: Vec<Vec<(u32, BagId)>>,
+    contained_by: Vec<Vec<(u32, BagId)>>,
+    totals: RefCell<HashMap<BagId, u64>>,
+}
+
+pub type BagId = usize;
+
+/// Bags that end up holding themselves, starting and ending with the same colour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle(pub Vec<String>);
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bags contain themselves: {}", self.0.join(" -> "))
     }
+}
 
-    let mut seed = holds_key
-        .get("shiny gold")
-        .expect("Should be a shiny gold")
-        .clone();
-    let mut last = 0;
+impl std::error::Error for Cycle {}
 
-    while last != seed.len() {
-        last = seed.len();
-        for s in seed.clone().iter() {
-            if let Some(c) = holds_key.get(s) {
-                seed.extend(c);
+impl BagGraph {
+    fn from_rules(rules: &[Rule]) -> Self {
+        let mut graph = BagGraph::default();
+        for rule in rules {
+            let outer = graph.intern(&rule.color);
+            for (count, color) in rule.contains.iter() {
+                let inner = graph.intern(color);
+                graph.contains[outer].push((*count, inner));
+                graph.contained_by[inner].push((*count, outer));
             }
         }
+        graph
     }
 
-    seed.len() as u32
-}
+    fn intern(&mut self, color: &str) -> BagId {
+        if let Some(id) = self.ids.get(color) {
+            return *id;
+        }
+        let id = self.names.len();
+        self.names.push(color.to_string());
+        self.ids.insert(color.to_string(), id);
+        self.contains.push(vec![]);
+        self.contained_by.push(vec![]);
+        id
+    }
 
-#[derive(Debug)]
-struct BagHolder<'a> {
-    queue: VecDeque<(u32, &'a str)>,
-    count: u32,
-}
+    pub fn id(&self, color: &str) -> Option<BagId> {
+        self.ids.get(color).cloned()
+    }
 
-impl<'a> BagHolder<'a> {
-    fn new(count: u32, first: &'a str) -> Self {
-        let mut queue: VecDeque<(u32, &str)> = VecDeque::new();
-        queue.push_back((count, first));
-        BagHolder { count: 0, queue }
+    /// Like `id`, but an error naming the colour when no rule mentions it.
+    pub fn lookup(&self, color: &str) -> Result<BagId> {
+        self.id(color)
+            .ok_or_else(|| eyre!("no rule mentions a {} bag", color))
     }
 
-    fn add(&mut self, outer: u32, count: u32, next: &'a str) {
-        let boxes = outer * count;
-        self.queue.push_back((boxes, next));
-        self.count += boxes;
+    pub fn name(&self, id: BagId) -> &str {
+        &self.names[id]
     }
 
-    fn next(&mut self) -> Option<(u32, &'a str)> {
-        self.queue.pop_front()
+    pub fn len(&self) -> usize {
+        self.names.len()
     }
-}
 
-#[aoc(day7, part2)]
-fn solve_part2(input: &[Rule]) -> u32 {
-    let map: HashMap<&str, Vec<(u32, &str)>> = input
-        .iter()
-        .map(|r| {
-            (
-                r.color.as_str(),
-                r.contains
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// What `id` directly holds, and how many of each.
+    pub fn children(&self, id: BagId) -> &[(u32, BagId)] {
+        &self.contains[id]
+    }
+
+    fn reachable(edges: &[Vec<(u32, BagId)>], id: BagId) -> BTreeSet<BagId> {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            for (_, other) in edges[next].iter() {
+                if seen.insert(*other) {
+                    stack.push(*other);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Every bag that can end up holding `id`.
+    pub fn ancestors(&self, id: BagId) -> BTreeSet<BagId> {
+        Self::reachable(&self.contained_by, id)
+    }
+
+    /// Every bag `id` ends up holding.
+    pub fn descendants(&self, id: BagId) -> BTreeSet<BagId> {
+        Self::reachable(&self.contains, id)
+    }
+
+    /// The first cycle found, if any bag can end up holding itself.
+    pub fn find_cycle(&self) -> Option<Cycle> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            Open,
+            Done,
+        }
+
+        fn visit(graph: &BagGraph, id: BagId, marks: &mut [Mark], path: &mut Vec<BagId>) -> bool {
+            marks[id] = Mark::Open;
+            path.push(id);
+            for (_, next) in graph.contains[id].iter() {
+                let mark = marks[*next];
+                if mark == Mark::Open {
+                    path.push(*next);
+                    return true;
+                }
+                if mark == Mark::New && visit(graph, *next, marks, path) {
+                    return true;
+                }
+            }
+            path.pop();
+            marks[id] = Mark::Done;
+            false
+        }
+
+        let mut marks = vec![Mark::New; self.len()];
+        for id in 0..self.len() {
+            let mut path = vec![];
+            if marks[id] == Mark::New && visit(self, id, &mut marks, &mut path) {
+                let last = *path.last().unwrap();
+                let start = path.iter().position(|p| *p == last).unwrap();
+                return Some(Cycle(
+                    path[start..]
+                        .iter()
+                        .map(|p| self.names[*p].clone())
+                        .collect(),
+                ));
+            }
+        }
+        None
+    }
+
+    /// How many bags end up inside `id`, remembered across calls.
+    pub fn total_contents(&self, id: BagId) -> Result<u64, Cycle> {
+        self.total(id, &mut vec![], &mut HashSet::new())
+    }
+
+    // `path` is the chain of bags being totalled right now; meeting one of them again is a cycle.
+    fn total(
+        &self,
+        id: BagId,
+        path: &mut Vec<BagId>,
+        on_path: &mut HashSet<BagId>,
+    ) -> Result<u64, Cycle> {
+        if let Some(total) = self.totals.borrow().get(&id) {
+            return Ok(*total);
+        }
+        if on_path.contains(&id) {
+            let start = path.iter().position(|p| *p == id).unwrap();
+            return Err(Cycle(
+                path[start..]
                     .iter()
-                    .map(|(u, s)| (*u, s.as_str()))
-                    .collect::<Vec<(u32, &str)>>(),
-            )
-        })
-        .collect();
-    let mut holder = BagHolder::new(1, "shiny gold");
-    while let Some((count, color)) = holder.next() {
-        if let Some(contains) = map.get(color) {
-            for (i, c) in contains.iter() {
-                holder.add(count, *i, c);
+                    .chain(std::iter::once(&id))
+                    .map(|p| self.names[*p].clone())
+                    .collect(),
+            ));
+        }
+        path.push(id);
+        on_path.insert(id);
+        let mut total = 0;
+        for (count, inner) in self.contains[id].iter() {
+            total += *count as u64 * (1 + self.total(*inner, path, on_path)?);
+        }
+        path.pop();
+        on_path.remove(&id);
+        self.totals.borrow_mut().insert(id, total);
+        Ok(total)
+    }
+
+    /// The graph in Graphviz DOT, edges labelled with counts.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph bags {\n");
+        for (id, name) in self.names.iter().enumerate() {
+            dot.push_str(&format!("    {} [label={:?}];\n", id, name));
+        }
+        for (id, edges) in self.contains.iter().enumerate() {
+            for (count, inner) in edges {
+                dot.push_str(&format!("    {} -> {} [label=\"{}\"];\n", id, inner, count));
             }
-            println!("{:?}", holder);
         }
+        dot.push('}');
+        dot
+    }
+}
+
+impl FromStr for BagGraph {
+    type Err = RuleErrors;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(BagGraph::from_rules(&parse_rules(s)?))
     }
+}
 
-    holder.count
+/// How many colours can end up holding a `target` bag.
+pub fn holders_of(graph: &BagGraph, target: &str) -> Result<usize> {
+    Ok(graph.ancestors(graph.lookup(target)?).len())
+}
+
+/// How many bags a `target` bag ends up holding.
+pub fn contents_of(graph: &BagGraph, target: &str) -> Result<u64> {
+    Ok(graph.total_contents(graph.lookup(target)?)?)
+}
+
+#[aoc(day7, part1)]
+fn solve_part1(input: &[Rule]) -> Result<usize> {
+    holders_of(&BagGraph::from_rules(input), "shiny gold")
+}
+
+#[aoc(day7, part2)]
+fn solve_part2(input: &[Rule]) -> Result<u64> {
+    contents_of(&BagGraph::from_rules(input), "shiny gold")
 }
 
 #[cfg(test)]
@@ -143,6 +381,16 @@ mod test {
 
     use super::*;
 
+    const SAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
     #[test]
     fn test_color() -> Result<()> {
         let (_, bag_color) =
@@ -157,7 +405,7 @@ mod test {
         let (input, _) = color("light red bags contain 1 bright white bag, 2 muted yellow bags.")?;
         let (_, contains) = contains(input)?;
 
-        assert_eq!(contains.get(0).unwrap(), &("1", "bright white"));
+        assert_eq!(contains.first().unwrap(), &("1", "bright white"));
 
         Ok(())
     }
@@ -172,7 +420,10 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
-        assert_eq!(solve_part1(generator(input).unwrap().as_slice()), 4);
+        assert_eq!(
+            solve_part1(generator(input).unwrap().as_slice()).unwrap(),
+            4
+        );
     }
     #[test]
     fn sample_part2() {
@@ -185,7 +436,10 @@ dark olive bags contain 3 faded blue bags, 4 dotted black bags.
 vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
-        assert_eq!(solve_part2(generator(input).unwrap().as_slice()), 32);
+        assert_eq!(
+            solve_part2(generator(input).unwrap().as_slice()).unwrap(),
+            32
+        );
     }
 
     #[test]
@@ -197,6 +451,107 @@ dark yellow bags contain 2 dark green bags.
 dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
-        assert_eq!(solve_part2(generator(input).unwrap().as_slice()), 126);
+        assert_eq!(
+            solve_part2(generator(input).unwrap().as_slice()).unwrap(),
+            126
+        );
+    }
+
+    #[test]
+    fn graph_queries() -> Result<()> {
+        let graph = SAMPLE.parse::<BagGraph>()?;
+        let id = |color| graph.id(color).unwrap();
+        let names = |ids: BTreeSet<BagId>| {
+            let mut names: Vec<_> = ids.into_iter().map(|i| graph.name(i)).collect();
+            names.sort_unstable();
+            names
+        };
+        assert_eq!(
+            names(graph.ancestors(id("shiny gold"))),
+            vec!["bright white", "dark orange", "light red", "muted yellow"]
+        );
+        assert_eq!(
+            names(graph.descendants(id("shiny gold"))),
+            vec!["dark olive", "dotted black", "faded blue", "vibrant plum"]
+        );
+        assert_eq!(graph.total_contents(id("dark olive"))?, 7);
+        assert_eq!(graph.total_contents(id("faded blue"))?, 0);
+        assert_eq!(graph.find_cycle(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn cycles() -> Result<()> {
+        let graph = "red bags contain 1 green bag.
+green bags contain 2 blue bags.
+blue bags contain 1 red bag, 1 white bag.
+white bags contain no other bags."
+            .parse::<BagGraph>()?;
+        let cycle = graph.find_cycle().unwrap();
+        assert_eq!(cycle.0, vec!["red", "green", "blue", "red"]);
+        assert_eq!(graph.total_contents(graph.id("red").unwrap()), Err(cycle));
+        assert_eq!(graph.total_contents(graph.id("white").unwrap()), Ok(0));
+        Ok(())
+    }
+
+    #[test]
+    fn dot() -> Result<()> {
+        let graph = "red bags contain 2 blue bags.\nblue bags contain no other bags."
+            .parse::<BagGraph>()?;
+        assert_eq!(
+            graph.to_dot(),
+            "digraph bags {
+    0 [label=\"red\"];
+    1 [label=\"blue\"];
+    0 -> 1 [label=\"2\"];
+}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let errors = parse_rules(
+            "red bags contain 1 blue bag.
+green bags hold 2 blue bags.
+blue bags contain no other bags.
+white bags contain 99999999999 red bags.
+black bags contain 1 red bag. extra",
+        )
+        .unwrap_err();
+        let lines: Vec<_> = errors.0.iter().map(|e| (e.line, e.span.clone())).collect();
+        assert_eq!(lines, vec![(2, 11..15), (4, 19..30), (5, 30..35)]);
+        assert_eq!(
+            errors.0[1].to_string(),
+            "line 4: bad count: number too large to fit in target type
+    white bags contain 99999999999 red bags.
+                       ^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn missing_target() -> Result<()> {
+        let graph = "red bags contain 2 blue bags.".parse::<BagGraph>()?;
+        assert_eq!(
+            graph.lookup("shiny gold").unwrap_err().to_string(),
+            "no rule mentions a shiny gold bag"
+        );
+        assert_eq!(graph.lookup("blue")?, 1);
+        assert!(contents_of(&graph, "shiny gold").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn other_targets() -> Result<()> {
+        let graph = SAMPLE.parse::<BagGraph>()?;
+        assert_eq!(holders_of(&graph, "dark olive")?, 5);
+        assert_eq!(contents_of(&graph, "dark olive")?, 7);
+        assert_eq!(holders_of(&graph, "light red")?, 0);
+        assert_eq!(contents_of(&graph, "muted yellow")?, 2 * (1 + 32) + 9);
+        assert_eq!(
+            holders_of(&graph, "plaid red").unwrap_err().to_string(),
+            "no rule mentions a plaid red bag"
+        );
+        Ok(())
     }
 }
@@ -1,51 +1,101 @@
-use std::iter::Iterator;
+use eyre::{eyre, Result};
+use std::collections::{HashMap, VecDeque};
 type GeneratorType = i64;
 
+/// How many numbers come before the first one that has to be checked.
+pub const PREAMBLE: usize = 25;
+
 #[aoc_generator(day9)]
-fn generator(input: &str) -> Vec<GeneratorType> {
-    input.split('\n').map(|s| s.parse().unwrap()).collect()
+fn generator(input: &str) -> Result<Vec<GeneratorType>> {
+    input
+        .split('\n')
+        .enumerate()
+        .map(|(idx, s)| {
+            s.parse()
+                .map_err(|e| eyre!("line {}: {:?} {}", idx + 1, s, e))
+        })
+        .collect()
+}
+
+/// The last `size` numbers and how many pairs of differing values sum to each total.
+#[derive(Debug, Clone)]
+pub struct PairSums {
+    size: usize,
+    window: VecDeque<GeneratorType>,
+    sums: HashMap<GeneratorType, usize>,
 }
 
-fn xmas_invalid(input: &[GeneratorType], consider: usize) -> i64 {
-    *input
-        .windows(consider + 1)
-        .find(|window| {
-            let (preamble, candidate) = window.split_at(consider);
-            let candidate = candidate[0];
-            for v in preamble {
-                if preamble.contains(&(candidate - v)) && *v != (candidate - v) {
-                    return false;
-                }
+impl PairSums {
+    pub fn new(size: usize) -> Self {
+        PairSums {
+            size,
+            window: VecDeque::with_capacity(size + 1),
+            sums: HashMap::new(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.window.len() == self.size
+    }
+
+    pub fn contains(&self, sum: GeneratorType) -> bool {
+        self.sums.get(&sum).is_some_and(|count| *count > 0)
+    }
+
+    /// Adds `value`, dropping the oldest number once the window is full.
+    pub fn push(&mut self, value: GeneratorType) {
+        if self.is_full() {
+            let oldest = self.window.pop_front().unwrap();
+            for other in self.window.iter().filter(|other| **other != oldest) {
+                *self.sums.get_mut(&(oldest + other)).unwrap() -= 1;
             }
-            true
-        })
-        .unwrap()
-        .iter()
-        .last()
-        .unwrap()
+        }
+        for other in self.window.iter().filter(|other| **other != value) {
+            *self.sums.entry(value + other).or_insert(0) += 1;
+        }
+        self.window.push_back(value);
+    }
 }
 
-fn weakness(input: &[GeneratorType], target: i64) -> i64 {
-    // Solving this with brute force, will come up with a better answer later maybe.
-    for window_size in 2..input.len() {
-        if let Some(window) = input.windows(window_size).find(|window| {
-            let check: i64 = window.iter().sum();
-            check == target
-        }) {
-            return window.iter().max().unwrap() + window.iter().min().unwrap();
+/// The first number after the preamble that isn't the sum of two of the `preamble` before it.
+pub fn xmas_invalid(input: &[GeneratorType], preamble: usize) -> Option<GeneratorType> {
+    let mut sums = PairSums::new(preamble);
+    for value in input {
+        if sums.is_full() && !sums.contains(*value) {
+            return Some(*value);
         }
+        sums.push(*value);
     }
-    0
+    None
+}
+
+/// Smallest plus largest of the first run of at least two numbers summing to `target`.
+/// The two-pointer walk assumes no number is negative.
+pub fn weakness(input: &[GeneratorType], target: GeneratorType) -> Option<GeneratorType> {
+    let (mut start, mut sum) = (0, 0);
+    for end in 0..input.len() {
+        sum += input[end];
+        while sum > target && start < end {
+            sum -= input[start];
+            start += 1;
+        }
+        if sum == target && end > start {
+            let run = &input[start..=end];
+            return Some(run.iter().min()? + run.iter().max()?);
+        }
+    }
+    None
 }
 
 #[aoc(day9, part1)]
-fn solve_part1(input: &[GeneratorType]) -> i64 {
-    xmas_invalid(input, 25)
+fn solve_part1(input: &[GeneratorType]) -> Result<GeneratorType> {
+    xmas_invalid(input, PREAMBLE).ok_or_else(|| eyre!("Every number is valid"))
 }
 
 #[aoc(day9, part2)]
-fn solve_part2(input: &[GeneratorType]) -> i64 {
-    weakness(input, xmas_invalid(input, 25))
+fn solve_part2(input: &[GeneratorType]) -> Result<GeneratorType> {
+    let target = solve_part1(input)?;
+    weakness(input, target).ok_or_else(|| eyre!("No run sums to {}", target))
 }
 
 #[cfg(test)]
@@ -74,17 +124,43 @@ mod test {
 576";
 
     #[test]
-    fn test_part1() {
-        assert_eq!(xmas_invalid(generator(SAMPLE).as_slice(), 5), 127);
+    fn test_part1() -> Result<()> {
+        assert_eq!(xmas_invalid(generator(SAMPLE)?.as_slice(), 5), Some(127));
+        Ok(())
     }
     #[test]
-    fn test_part2() {
+    fn test_part2() -> Result<()> {
         assert_eq!(
             weakness(
-                generator(SAMPLE).as_slice(),
-                xmas_invalid(generator(SAMPLE).as_slice(), 5)
+                generator(SAMPLE)?.as_slice(),
+                xmas_invalid(generator(SAMPLE)?.as_slice(), 5).unwrap()
             ),
-            62
+            Some(62)
         );
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_found() -> Result<()> {
+        let input = generator(SAMPLE)?;
+        assert_eq!(xmas_invalid(&input[..14], 5), None);
+        assert_eq!(xmas_invalid(&input, 40), None);
+        // A single number isn't a run.
+        assert_eq!(weakness(&input, 47), None);
+        assert_eq!(weakness(&input, 1), None);
+        assert!(generator("1\nx").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pair_sums() {
+        let mut sums = PairSums::new(3);
+        for v in &[1, 2, 2, 5] {
+            sums.push(*v);
+        }
+        // The window is 2, 2, 5 now; equal values don't pair up.
+        assert!(sums.contains(7));
+        assert!(!sums.contains(3));
+        assert!(!sums.contains(4));
     }
 }
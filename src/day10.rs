@@ -1,59 +1,157 @@
-use std::cell::Cell;
+use eyre::{eyre, Result};
+use std::collections::{BTreeMap, BTreeSet};
 
 type GeneratorType = u64;
 
 #[aoc_generator(day10)]
-fn generator(input: &str) -> Vec<GeneratorType> {
-    input.split('\n').map(|s| s.parse().unwrap()).collect()
+fn generator(input: &str) -> Result<Vec<GeneratorType>> {
+    input
+        .split('\n')
+        .enumerate()
+        .map(|(idx, s)| {
+            s.parse()
+                .map_err(|e| eyre!("line {}: {:?} {}", idx + 1, s, e))
+        })
+        .collect()
 }
 
 #[aoc(day10, part1)]
-fn solve_part1(input: &[GeneratorType]) -> u64 {
-    let mut input = input.to_vec();
-    input.push(0);
-    input.push(input.iter().max().unwrap() + 3);
-    input.sort_unstable();
-    let (ones, threes) =
-        input
-            .windows(2)
-            .map(|pair| pair[1] - pair[0])
-            .fold((0, 0), |(o, t), v| {
-                if v == 1 {
-                    (o + 1, t)
-                } else if v == 3 {
-                    (o, t + 1)
-                } else {
-                    (o, t)
-                }
-            });
-    println!("ones: {}, threes: {}", ones, threes);
-    ones * threes
+fn solve_part1(input: &[GeneratorType]) -> Result<usize> {
+    let gaps = Rules::default().gaps(input);
+    if let Some(gap) = gaps
+        .keys()
+        .find(|gap| !Rules::default().steps.contains(gap))
+    {
+        return Err(eyre!(
+            "The adapters can't all be chained, there is a gap of {}",
+            gap
+        ));
+    }
+    Ok(gaps.get(&1).unwrap_or(&0) * gaps.get(&3).unwrap_or(&0))
 }
 
 #[aoc(day10, part2)]
-fn solve_part2(input: &[GeneratorType]) -> u64 {
-    let mut input = input.to_vec();
-    input.push(0);
-    input.push(input.iter().max().unwrap() + 3);
-    input.sort_unstable();
-    let mut input = input
-        .iter()
-        .map(|v| (*v, Cell::new(0)))
-        .collect::<Vec<(u64, Cell<u64>)>>();
-    input.get_mut(0).unwrap().1.set(1);
-
-    for (index, (volt, paths)) in input.iter().enumerate() {
-        for window in 1..=3 {
-            if let Some((next_volt, next_paths)) = input.get(index + window) {
-                if *next_volt - *volt <= 3 {
-                    let val = next_paths.get();
-                    next_paths.set(val + paths.get());
-                }
+fn solve_part2(input: &[GeneratorType]) -> Result<u128> {
+    Rules::default()
+        .count_chains(input)
+        .ok_or_else(|| eyre!("Too many arrangements to count"))
+}
+
+/// Which joltage jumps an adapter can take, and what sits at either end of a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    pub steps: BTreeSet<GeneratorType>,
+    pub outlet: GeneratorType,
+    pub device_offset: GeneratorType,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules::new(1..=3, 0, 3)
+    }
+}
+
+impl Rules {
+    /// A step of 0 is dropped, it would let a chain stand still.
+    pub fn new(
+        steps: impl IntoIterator<Item = GeneratorType>,
+        outlet: GeneratorType,
+        device_offset: GeneratorType,
+    ) -> Self {
+        Rules {
+            steps: steps.into_iter().filter(|s| *s > 0).collect(),
+            outlet,
+            device_offset,
+        }
+    }
+
+    /// The outlet, every adapter in order, then the device. Adapters below the outlet can
+    /// never be reached, so they are left out.
+    pub fn joltages(&self, adapters: &[GeneratorType]) -> Vec<GeneratorType> {
+        let mut joltages: Vec<_> = adapters
+            .iter()
+            .filter(|a| **a >= self.outlet)
+            .cloned()
+            .collect();
+        joltages.sort_unstable();
+        let device = joltages.last().unwrap_or(&self.outlet) + self.device_offset;
+        joltages.insert(0, self.outlet);
+        joltages.push(device);
+        joltages
+    }
+
+    /// How often each gap shows up when every adapter is used.
+    pub fn gaps(&self, adapters: &[GeneratorType]) -> BTreeMap<GeneratorType, usize> {
+        let mut gaps = BTreeMap::new();
+        for pair in self.joltages(adapters).windows(2) {
+            *gaps.entry(pair[1] - pair[0]).or_insert(0) += 1;
+        }
+        gaps
+    }
+
+    /// For each joltage, the later ones one step away.
+    fn next(&self, joltages: &[GeneratorType]) -> Vec<Vec<usize>> {
+        let longest = self.steps.iter().last().cloned().unwrap_or(0);
+        (0..joltages.len())
+            .map(|from| {
+                (from + 1..joltages.len())
+                    .take_while(|to| joltages[*to] - joltages[from] <= longest)
+                    .filter(|to| self.steps.contains(&(joltages[*to] - joltages[from])))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// How many ways there are to get from the outlet to the device, or `None` on overflow.
+    pub fn count_chains(&self, adapters: &[GeneratorType]) -> Option<u128> {
+        let joltages = self.joltages(adapters);
+        let next = self.next(&joltages);
+        let mut paths = vec![0u128; joltages.len()];
+        paths[0] = 1;
+        for from in 0..joltages.len() {
+            for to in next[from].iter() {
+                paths[*to] = paths[*to].checked_add(paths[from])?;
             }
         }
+        paths.last().cloned()
     }
 
-    input.last().unwrap().1.get()
+    /// Every chain from the outlet to the device, lazily; `take` a few to sample.
+    pub fn chains(&self, adapters: &[GeneratorType]) -> Chains {
+        let joltages = self.joltages(adapters);
+        Chains {
+            next: self.next(&joltages),
+            stack: vec![vec![0]],
+            joltages,
+        }
+    }
+}
+
+/// Depth first walk over the chains, see `Rules::chains`.
+pub struct Chains {
+    joltages: Vec<GeneratorType>,
+    next: Vec<Vec<usize>>,
+    stack: Vec<Vec<usize>>,
+}
+
+impl Iterator for Chains {
+    type Item = Vec<GeneratorType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.joltages.len() - 1;
+        while let Some(path) = self.stack.pop() {
+            let last = *path.last().unwrap();
+            if last == end {
+                return Some(path.iter().map(|i| self.joltages[*i]).collect());
+            }
+            for to in self.next[last].iter().rev() {
+                let mut longer = path.clone();
+                longer.push(*to);
+                self.stack.push(longer);
+            }
+        }
+        None
+    }
 }
 
 #[cfg(test)]
@@ -105,15 +203,81 @@ mod test {
 3";
 
     #[test]
-    fn test_part1() {
-        assert_eq!(solve_part1(generator(SAMPLE).as_slice()), (7 * 5));
+    fn test_part1() -> Result<()> {
+        assert_eq!(solve_part1(generator(SAMPLE)?.as_slice())?, (7 * 5));
+        Ok(())
+    }
+    #[test]
+    fn test_part2() -> Result<()> {
+        assert_eq!(solve_part2(generator(SAMPLE)?.as_slice())?, 8);
+        Ok(())
+    }
+    #[test]
+    fn test_part2_long() -> Result<()> {
+        assert_eq!(solve_part2(generator(LONG)?.as_slice())?, 19208);
+        Ok(())
+    }
+
+    #[test]
+    fn histogram() -> Result<()> {
+        let gaps = Rules::default().gaps(&generator(LONG)?);
+        assert_eq!(gaps, vec![(1, 22), (3, 10)].into_iter().collect());
+        assert!(solve_part1(&[1, 8]).is_err());
+        Ok(())
     }
+
     #[test]
-    fn test_part2() {
-        assert_eq!(solve_part2(generator(SAMPLE).as_slice()), 8);
+    fn enumerate_chains() -> Result<()> {
+        let input = generator(SAMPLE)?;
+        let chains: Vec<_> = Rules::default().chains(&input).collect();
+        assert_eq!(chains.len(), 8);
+        assert_eq!(
+            chains[0],
+            vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]
+        );
+        assert_eq!(chains[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        let long = generator(LONG)?;
+        assert_eq!(Rules::default().chains(&long).take(5).count(), 5);
+        Ok(())
     }
+
+    #[test]
+    fn other_rules() {
+        // Only even steps, from an outlet at 10, with the device right after the last adapter.
+        let rules = Rules::new(vec![0, 2, 4], 10, 2);
+        assert_eq!(rules.count_chains(&[12, 13, 14, 16]), Some(5));
+        assert_eq!(
+            rules.chains(&[12, 13, 14, 16]).collect::<Vec<_>>(),
+            vec![
+                vec![10, 12, 14, 16, 18],
+                vec![10, 12, 14, 18],
+                vec![10, 12, 16, 18],
+                vec![10, 14, 16, 18],
+                vec![10, 14, 18],
+            ]
+        );
+    }
+
+    #[test]
+    fn below_outlet() {
+        let rules = Rules::new(1..=3, 10, 3);
+        let adapters = [4, 11, 12, 1];
+        assert_eq!(rules.joltages(&adapters), vec![10, 11, 12, 15]);
+        assert_eq!(
+            rules.gaps(&adapters),
+            vec![(1, 2), (3, 1)].into_iter().collect()
+        );
+        assert_eq!(rules.count_chains(&adapters), Some(2));
+        assert_eq!(rules.chains(&[3]).collect::<Vec<_>>(), vec![vec![10, 13]]);
+    }
+
     #[test]
-    fn test_part2_long() {
-        assert_eq!(solve_part2(generator(LONG).as_slice()), 19208);
+    fn no_overflow() {
+        // Every adapter one jolt apart, the count grows like tribonacci.
+        let adapters: Vec<_> = (1..=120).collect();
+        let count = Rules::default().count_chains(&adapters).unwrap();
+        assert!(count > u64::MAX as u128);
+        let adapters: Vec<_> = (1..=200).collect();
+        assert_eq!(Rules::default().count_chains(&adapters), None);
     }
 }
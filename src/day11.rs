@@ -1,230 +1,241 @@
-type GeneratorType = Vec<Element>;
-
-#[derive(Debug, Clone, PartialEq)]
-struct Position {
-    x: usize,
-    y: usize,
-    element: Element,
-}
+use eyre::{eyre, Result};
+use std::{collections::HashMap, fmt};
 
 #[derive(Debug, Clone, PartialEq, Copy)]
-enum Element {
-    Empyt,
+pub enum Element {
+    Empty,
     Taken,
     Floor,
 }
 
 #[aoc_generator(day11)]
-fn generator(input: &str) -> Vec<GeneratorType> {
-    input
+fn generator(input: &str) -> Layout {
+    let cells: Vec<Vec<_>> = input
         .split('\n')
         .map(|line| {
             line.chars()
                 .map(|elem| match elem {
-                    'L' => Element::Empyt,
+                    'L' => Element::Empty,
                     '#' => Element::Taken,
                     _ => Element::Floor,
                 })
                 .collect::<Vec<_>>()
         })
-        .collect()
+        .collect();
+    Layout {
+        width: cells.iter().map(Vec::len).max().unwrap_or(0),
+        cells,
+    }
 }
 
-fn run_seats(
-    input: &[GeneratorType],
-    search: fn(x: usize, y: usize, input: &[GeneratorType]) -> Vec<Element>,
-    tolerence: usize,
-) -> Vec<GeneratorType> {
-    let mut output = input.to_vec();
-    for (row_idx, row) in output.iter_mut().enumerate() {
-        for (col_idx, elm) in row.iter_mut().enumerate() {
-            if col_idx == 9 && row_idx == 0 {
-                //println!("{:?}", search(col_idx, row_idx, input));
-            }
-            match elm {
-                Element::Empyt => {
-                    if search(col_idx, row_idx, input)
-                        .iter()
-                        .all(|e| e != &Element::Taken)
-                    {
-                        *elm = Element::Taken;
-                    }
-                }
-                Element::Taken => {
-                    if search(col_idx, row_idx, input)
-                        .iter()
-                        .filter(|e| *e == &Element::Taken)
-                        .count()
-                        >= tolerence
-                    {
-                        *elm = Element::Empyt;
-                    }
-                }
-                Element::Floor => {}
-            }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    width: usize,
+    cells: Vec<Vec<Element>>,
+}
+
+impl Layout {
+    fn get(&self, x: isize, y: isize) -> Option<Element> {
+        if x < 0 || y < 0 {
+            return None;
         }
+        self.cells
+            .get(y as usize)
+            .map(|row| *row.get(x as usize).unwrap_or(&Element::Floor))
+            .filter(|_| (x as usize) < self.width)
     }
-    output
 }
 
-#[aoc(day11, part1)]
-fn solve_part1(input: &[GeneratorType]) -> usize {
-    let mut last = input.to_vec();
-    loop {
-        let this = run_seats(
-            last.as_slice(),
-            |x, y, input| {
-                let x = x as isize;
-                let y = y as isize;
-                vec![
-                    (x, y + 1),
-                    (x + 1, y),
-                    (x - 1, y),
-                    (x, y - 1),
-                    (x + 1, y - 1),
-                    (x - 1, y + 1),
-                    (x + 1, y + 1),
-                    (x - 1, y - 1),
-                ]
-                .iter()
-                .filter(|(x, y)| *x >= 0 && *y >= 0)
-                .map(|(x, y)| (*x as usize, *y as usize))
-                .filter_map(|(x, y)| input.get(y).and_then(|y| y.get(x)))
-                .cloned()
-                .collect::<Vec<_>>()
-            },
-            4,
-        );
-        if this == last {
-            break last
-                .iter()
-                .flatten()
-                .filter(|elm| **elm == Element::Taken)
-                .count();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// The eight seats around.
+    Adjacent,
+    /// The first seat seen in each of the eight directions, looking over the floor.
+    LineOfSight,
+}
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// An empty seat is taken when at most `lonely` neighbours are, and a taken one
+/// is left when at least `crowded` are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub neighbourhood: Neighbourhood,
+    pub lonely: usize,
+    pub crowded: usize,
+}
+
+impl Rules {
+    pub fn part1() -> Self {
+        Rules {
+            neighbourhood: Neighbourhood::Adjacent,
+            lonely: 0,
+            crowded: 4,
+        }
+    }
+
+    pub fn part2() -> Self {
+        Rules {
+            neighbourhood: Neighbourhood::LineOfSight,
+            lonely: 0,
+            crowded: 5,
         }
-        last = this;
     }
 }
 
-#[aoc(day11, part2)]
-fn solve_part2(input: &[GeneratorType]) -> usize {
-    let mut last = input.to_vec();
-    loop {
-        let this = run_seats(
-            last.as_slice(),
-            |x, y, input| {
-                let mut output: Vec<Element> = Vec::new();
-
-                let mut count = 1;
-                loop {
-                    match input.get(y).and_then(|y| y.get(x + count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count = 1;
-                loop {
-                    match input.get(y + count).and_then(|y| y.get(x)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count = 1;
-                loop {
-                    match input.get(y + count).and_then(|y| y.get(x + count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count: usize = 1;
-                while y as isize - count as isize >= 0 {
-                    match input.get(y - count).and_then(|y| y.get(x + count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count: usize = 1;
-                while y as isize - count as isize >= 0 {
-                    match input.get(y - count).and_then(|y| y.get(x)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count: usize = 1;
-                while y as isize - count as isize >= 0 && x as isize - count as isize >= 0 {
-                    match input.get(y - count).and_then(|y| y.get(x - count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count: usize = 1;
-                while x as isize - count as isize >= 0 {
-                    match input.get(y + count).and_then(|y| y.get(x - count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
-                }
-                let mut count: usize = 1;
-                while x as isize - count as isize >= 0 {
-                    match input.get(y).and_then(|y| y.get(x - count)) {
-                        Some(elm) if *elm != Element::Floor => {
-                            output.push(*elm);
-                            break;
-                        }
-                        None => break,
-                        _ => {}
-                    }
-                    count += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing changes any more after `steps`.
+    Settled { steps: usize, occupied: usize },
+    /// From `start` on, the same `period` states repeat forever.
+    Oscillates { start: usize, period: usize },
+}
+
+/// Seats only, each with the list of seats it looks at, stepped between two buffers.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    rules: Rules,
+    positions: Vec<(usize, usize)>,
+    neighbours: Vec<Vec<usize>>,
+    current: Vec<bool>,
+    next: Vec<bool>,
+    steps: usize,
+}
+
+impl Simulator {
+    pub fn new(layout: &Layout, rules: Rules) -> Self {
+        let mut index = HashMap::new();
+        let mut positions = vec![];
+        let mut current = vec![];
+        for (y, row) in layout.cells.iter().enumerate() {
+            for (x, element) in row.iter().enumerate() {
+                if *element != Element::Floor {
+                    index.insert((x as isize, y as isize), positions.len());
+                    positions.push((x, y));
+                    current.push(*element == Element::Taken);
                 }
-                output
-            },
-            5,
-        );
-        if this == last {
-            break last
-                .iter()
-                .flatten()
-                .filter(|elm| **elm == Element::Taken)
-                .count();
+            }
         }
-        //println!("iteration {}\n{}\n", count, to_string(last.as_slice()));
-        last = this;
+
+        let reach = match rules.neighbourhood {
+            Neighbourhood::Adjacent => 1,
+            Neighbourhood::LineOfSight => isize::MAX,
+        };
+        let neighbours = positions
+            .iter()
+            .map(|(x, y)| {
+                DIRECTIONS
+                    .iter()
+                    .filter_map(|(dx, dy)| {
+                        (1..=reach)
+                            .map(|n| (*x as isize + dx * n, *y as isize + dy * n))
+                            .take_while(|(x, y)| layout.get(*x, *y).is_some())
+                            .find_map(|at| index.get(&at).cloned())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Simulator {
+            rules,
+            positions,
+            neighbours,
+            next: current.clone(),
+            current,
+            steps: 0,
+        }
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.current.iter().filter(|taken| **taken).count()
     }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Moves everyone once, returning whether anything changed.
+    pub fn step(&mut self) -> bool {
+        let mut changed = false;
+        for (seat, neighbours) in self.neighbours.iter().enumerate() {
+            let taken = neighbours.iter().filter(|n| self.current[**n]).count();
+            self.next[seat] = if self.current[seat] {
+                taken < self.rules.crowded
+            } else {
+                taken <= self.rules.lonely
+            };
+            changed |= self.next[seat] != self.current[seat];
+        }
+        std::mem::swap(&mut self.current, &mut self.next);
+        self.steps += 1;
+        changed
+    }
+
+    /// Steps until the seats settle or a state comes round again.
+    pub fn run(&mut self) -> Outcome {
+        let mut seen = HashMap::new();
+        seen.insert(self.current.clone(), self.steps);
+        loop {
+            if !self.step() {
+                return Outcome::Settled {
+                    steps: self.steps - 1,
+                    occupied: self.occupied(),
+                };
+            }
+            if let Some(start) = seen.insert(self.current.clone(), self.steps) {
+                return Outcome::Oscillates {
+                    start,
+                    period: self.steps - start,
+                };
+            }
+        }
+    }
+}
+
+impl fmt::Display for Simulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.positions.iter().map(|(x, _)| x + 1).max().unwrap_or(0);
+        let height = self.positions.iter().map(|(_, y)| y + 1).max().unwrap_or(0);
+        let mut grid = vec![vec!['.'; width]; height];
+        for ((x, y), taken) in self.positions.iter().zip(self.current.iter()) {
+            grid[*y][*x] = if *taken { '#' } else { 'L' };
+        }
+        let rows: Vec<String> = grid
+            .into_iter()
+            .map(|row| row.into_iter().collect())
+            .collect();
+        write!(f, "{}", rows.join("\n"))
+    }
+}
+
+fn settle(input: &Layout, rules: Rules) -> Result<usize> {
+    match Simulator::new(input, rules).run() {
+        Outcome::Settled { occupied, .. } => Ok(occupied),
+        Outcome::Oscillates { start, period } => Err(eyre!(
+            "Seats never settle, from step {} they repeat every {} steps",
+            start,
+            period
+        )),
+    }
+}
+
+#[aoc(day11, part1)]
+fn solve_part1(input: &Layout) -> Result<usize> {
+    settle(input, Rules::part1())
+}
+
+#[aoc(day11, part2)]
+fn solve_part2(input: &Layout) -> Result<usize> {
+    settle(input, Rules::part2())
 }
 
 #[cfg(test)]
@@ -243,11 +254,82 @@ L.LLLLLL.L
 L.LLLLL.LL";
 
     #[test]
-    fn test_part1() {
-        assert_eq!(solve_part1(generator(SAMPLE).as_slice()), 37);
+    fn test_part1() -> Result<()> {
+        assert_eq!(solve_part1(&generator(SAMPLE))?, 37);
+        Ok(())
+    }
+    #[test]
+    fn test_part2() -> Result<()> {
+        assert_eq!(solve_part2(&generator(SAMPLE))?, 26);
+        Ok(())
+    }
+
+    #[test]
+    fn neighbours() {
+        let layout = generator(
+            ".......#.
+...#.....
+.#.......
+.........
+..#L....#
+....#....
+.........
+#........
+...#.....",
+        );
+        let simulator = Simulator::new(&layout, Rules::part2());
+        let seat = simulator
+            .positions
+            .iter()
+            .position(|p| *p == (3, 4))
+            .unwrap();
+        assert_eq!(simulator.neighbours[seat].len(), 8);
+        let simulator = Simulator::new(&layout, Rules::part1());
+        assert_eq!(simulator.neighbours[seat].len(), 2);
+    }
+
+    #[test]
+    fn steps_and_render() {
+        let mut simulator = Simulator::new(&generator(SAMPLE), Rules::part1());
+        assert!(simulator.step());
+        assert!(simulator.step());
+        assert_eq!(
+            simulator.to_string(),
+            "#.LL.L#.##
+#LLLLLL.L#
+L.L.L..L..
+#LLL.LL.L#
+#.LL.LL.LL
+#.LLLL#.##
+..L.L.....
+#LLLLLLLL#
+#.LLLLLL.L
+#.#LLLL.##"
+        );
+        assert_eq!(
+            simulator.run(),
+            Outcome::Settled {
+                steps: 5,
+                occupied: 37
+            }
+        );
     }
+
     #[test]
-    fn test_part2() {
-        assert_eq!(solve_part2(generator(SAMPLE).as_slice()), 26);
+    fn oscillation() {
+        let rules = Rules {
+            neighbourhood: Neighbourhood::Adjacent,
+            lonely: 0,
+            crowded: 1,
+        };
+        let layout = generator("LL");
+        assert_eq!(
+            Simulator::new(&layout, rules).run(),
+            Outcome::Oscillates {
+                start: 0,
+                period: 2
+            }
+        );
+        assert!(settle(&layout, rules).is_err());
     }
 }
@@ -1,10 +1,10 @@
-use eyre::Error;
-use std::str::FromStr;
+use eyre::{eyre, Result};
+use std::{fmt, num::ParseIntError, str::FromStr};
 
 type GeneratorType = Command;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
-enum Command {
+pub enum Command {
     North(i64),
     South(i64),
     East(i64),
@@ -12,148 +12,261 @@ enum Command {
     Left(i64),
     Right(i64),
     Forward(i64),
+    Backward(i64),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    Letter(char),
+    Value(ParseIntError),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "empty command"),
+            CommandError::Letter(c) => write!(f, "unknown command {:?}", c),
+            CommandError::Value(e) => write!(f, "bad value: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
 impl FromStr for Command {
-    type Err = Error;
+    type Err = CommandError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (dir, val) = s.split_at(1);
+        let dir = s.chars().next().ok_or(CommandError::Empty)?;
+        let val = || s[dir.len_utf8()..].parse().map_err(CommandError::Value);
         Ok(match dir {
-            "N" => Command::North(val.parse()?),
-            "S" => Command::South(val.parse()?),
-            "E" => Command::East(val.parse()?),
-            "W" => Command::West(val.parse()?),
-            "L" => Command::Left(val.parse()?),
-            "R" => Command::Right(val.parse()?),
-            "F" => Command::Forward(val.parse()?),
-            _ => unreachable!("Not allowed by problem"),
+            'N' => Command::North(val()?),
+            'S' => Command::South(val()?),
+            'E' => Command::East(val()?),
+            'W' => Command::West(val()?),
+            'L' => Command::Left(val()?),
+            'R' => Command::Right(val()?),
+            'F' => Command::Forward(val()?),
+            'B' => Command::Backward(val()?),
+            c => return Err(CommandError::Letter(c)),
         })
     }
 }
 
+/// East and north of the origin, in whole units until a turn that isn't a quarter turn.
 #[derive(Debug, PartialEq, Copy, Clone)]
-struct Ship {
-    east: i64,
-    north: i64,
-    heading: i64,
+pub enum Point {
+    Exact { east: i64, north: i64 },
+    Approx { east: f64, north: f64 },
 }
 
-impl Ship {
-    fn new() -> Self {
-        Ship {
-            east: 0,
-            north: 0,
-            heading: 90,
+impl Default for Point {
+    fn default() -> Self {
+        Point::new(0, 0)
+    }
+}
+
+impl Point {
+    pub fn new(east: i64, north: i64) -> Self {
+        Point::Exact { east, north }
+    }
+
+    pub fn east(&self) -> f64 {
+        match *self {
+            Point::Exact { east, .. } => east as f64,
+            Point::Approx { east, .. } => east,
         }
     }
 
-    fn mv(&mut self, cmd: &Command) {
-        match cmd {
-            Command::North(val) => self.north += val,
-            Command::South(val) => self.north -= val,
-            Command::East(val) => self.east += val,
-            Command::West(val) => self.east -= val,
-            Command::Left(val) => {
-                let mut tmp = self.heading - val;
-                while tmp < 0 {
-                    tmp += 360;
-                }
-                self.heading = tmp;
-            }
-            Command::Right(val) => {
-                let mut tmp = self.heading + val;
-                while tmp >= 360 {
-                    tmp -= 360;
-                }
-                self.heading = tmp;
+    pub fn north(&self) -> f64 {
+        match *self {
+            Point::Exact { north, .. } => north as f64,
+            Point::Approx { north, .. } => north,
+        }
+    }
+
+    /// One unit along a compass heading, 0 being north and 90 east.
+    pub fn heading(degrees: i64) -> Self {
+        Point::new(0, 1).rotate(degrees)
+    }
+
+    /// Turned clockwise around the origin, exactly when `degrees` is a multiple of 90.
+    pub fn rotate(self, degrees: i64) -> Self {
+        let degrees = degrees.rem_euclid(360);
+        if degrees % 90 != 0 {
+            let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+            let (east, north) = (self.east(), self.north());
+            return Point::Approx {
+                east: east * cos + north * sin,
+                north: north * cos - east * sin,
+            };
+        }
+        (0..degrees / 90).fold(self, |p, _| match p {
+            Point::Exact { east, north } => Point::Exact {
+                east: north,
+                north: -east,
+            },
+            Point::Approx { east, north } => Point::Approx {
+                east: north,
+                north: -east,
+            },
+        })
+    }
+
+    fn step(self, by: Point, times: i64) -> Self {
+        match (self, by) {
+            (Point::Exact { east, north }, Point::Exact { east: e, north: n }) => {
+                Point::new(east + e * times, north + n * times)
             }
-            Command::Forward(val) => match self.heading {
-                0 => self.north += val,
-                90 => self.east += val,
-                180 => self.north -= val,
-                270 => self.east -= val,
-                v => unimplemented!("Shouldn't happen: {}", v),
+            _ => Point::Approx {
+                east: self.east() + by.east() * times as f64,
+                north: self.north() + by.north() * times as f64,
             },
         }
     }
 
-    fn pos(&self) -> i64 {
-        i64::abs(self.east) + i64::abs(self.north)
+    /// Rounded to the nearest unit once the point is approximate.
+    pub fn manhattan(&self) -> i64 {
+        match *self {
+            Point::Exact { east, north } => east.abs() + north.abs(),
+            Point::Approx { east, north } => (east.abs() + north.abs()).round() as i64,
+        }
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Point::Exact { east, north } => write!(f, "{},{}", east, north),
+            Point::Approx { east, north } => write!(f, "{},{}", east, north),
+        }
     }
 }
 
-#[derive(Debug)]
-struct Waypoint {
-    north: i64,
-    east: i64,
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Mode {
+    /// Compass moves shift the ship, turns change its heading.
+    Heading,
+    /// Compass moves shift the waypoint, turns swing it round the ship.
+    Waypoint,
 }
 
-impl Waypoint {
-    fn new() -> Self {
-        Waypoint { north: 1, east: 10 }
+/// Where everything was after one command.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct State {
+    pub position: Point,
+    /// Degrees clockwise from north, in `0..360`.
+    pub heading: i64,
+    pub waypoint: Point,
+}
+
+#[derive(Debug, Clone)]
+pub struct Navigator {
+    mode: Mode,
+    state: State,
+    trajectory: Vec<State>,
+}
+
+impl Navigator {
+    /// Facing east, with the waypoint 10 east and 1 north.
+    pub fn new(mode: Mode) -> Self {
+        let state = State {
+            position: Point::default(),
+            heading: 90,
+            waypoint: Point::new(10, 1),
+        };
+        Navigator {
+            mode,
+            state,
+            trajectory: vec![state],
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
     }
 
-    fn mv(&mut self, ship: &mut Ship, cmd: &Command) {
-        match cmd {
-            Command::North(val) => self.north += val,
-            Command::South(val) => self.north -= val,
-            Command::East(val) => self.east += val,
-            Command::West(val) => self.east -= val,
-            Command::Right(val) => {
-                let mut tmp = *val;
-                while tmp > 0 {
-                    let east = self.north;
-                    self.north = -self.east;
-                    self.east = east;
-                    tmp -= 90;
-                }
+    /// The starting state, then one state per command.
+    pub fn trajectory(&self) -> &[State] {
+        &self.trajectory
+    }
+
+    pub fn mv(&mut self, cmd: &Command) {
+        enum Action {
+            Shift(i64),
+            Ahead,
+            Turn,
+        }
+        let (action, val) = match *cmd {
+            Command::North(val) => (Action::Shift(0), val),
+            Command::East(val) => (Action::Shift(90), val),
+            Command::South(val) => (Action::Shift(180), val),
+            Command::West(val) => (Action::Shift(270), val),
+            Command::Right(val) => (Action::Turn, val),
+            Command::Left(val) => (Action::Turn, -val),
+            Command::Forward(val) => (Action::Ahead, val),
+            Command::Backward(val) => (Action::Ahead, -val),
+        };
+        let state = &mut self.state;
+        match (action, self.mode) {
+            (Action::Shift(dir), Mode::Heading) => {
+                state.position = state.position.step(Point::heading(dir), val)
+            }
+            (Action::Shift(dir), Mode::Waypoint) => {
+                state.waypoint = state.waypoint.step(Point::heading(dir), val)
             }
-            Command::Left(val) => {
-                let mut tmp = *val;
-                while tmp > 0 {
-                    let east = -self.north;
-                    self.north = self.east;
-                    self.east = east;
-                    tmp -= 90;
-                }
+            (Action::Ahead, Mode::Heading) => {
+                state.position = state.position.step(Point::heading(state.heading), val)
             }
-            Command::Forward(val) => {
-                ship.north += self.north * val;
-                ship.east += self.east * val;
+            (Action::Ahead, Mode::Waypoint) => {
+                state.position = state.position.step(state.waypoint, val)
             }
+            (Action::Turn, Mode::Heading) => state.heading = (state.heading + val).rem_euclid(360),
+            (Action::Turn, Mode::Waypoint) => state.waypoint = state.waypoint.rotate(val),
         }
+        self.trajectory.push(*state);
+    }
+
+    pub fn run<'a>(&mut self, commands: impl IntoIterator<Item = &'a Command>) -> &mut Self {
+        for cmd in commands {
+            self.mv(cmd);
+        }
+        self
+    }
+
+    /// The trajectory as `east,north,heading,waypoint_east,waypoint_north` rows.
+    pub fn trajectory_csv(&self) -> String {
+        let mut csv = String::from("east,north,heading,waypoint_east,waypoint_north\n");
+        for s in self.trajectory.iter() {
+            csv.push_str(&format!("{},{},{}\n", s.position, s.heading, s.waypoint));
+        }
+        csv
     }
 }
 
 #[aoc_generator(day12)]
-fn generator(input: &str) -> Vec<GeneratorType> {
+fn generator(input: &str) -> Result<Vec<GeneratorType>> {
     input
         .split('\n')
-        .map(|line| {
+        .enumerate()
+        .map(|(idx, line)| {
             line.parse::<Command>()
-                .expect("Everything in the file should be parsable")
+                .map_err(|e| eyre!("line {}: {:?} {}", idx + 1, line, e))
         })
         .collect()
 }
 
 #[aoc(day12, part1)]
 fn solve_part1(input: &[GeneratorType]) -> i64 {
-    let mut ship = Ship::new();
-    input.iter().for_each(|cmd| {
-        ship.mv(cmd);
-    });
-    ship.pos()
+    let mut ship = Navigator::new(Mode::Heading);
+    ship.run(input).state().position.manhattan()
 }
 
 #[aoc(day12, part2)]
 fn solve_part2(input: &[GeneratorType]) -> i64 {
-    let mut ship = Ship::new();
-    let mut waypoint = Waypoint::new();
-    input.iter().for_each(|cmd| {
-        waypoint.mv(&mut ship, cmd);
-    });
-    ship.pos()
+    let mut ship = Navigator::new(Mode::Waypoint);
+    ship.run(input).state().position.manhattan()
 }
 
 #[cfg(test)]
@@ -167,12 +280,69 @@ R90
 F11";
 
     #[test]
-    fn test_part1() {
-        let res = solve_part1(generator(SAMPLE).as_slice());
+    fn test_part1() -> Result<()> {
+        let res = solve_part1(generator(SAMPLE)?.as_slice());
         assert_eq!(res, 25);
+        Ok(())
+    }
+    #[test]
+    fn test_part2() -> Result<()> {
+        assert_eq!(solve_part2(generator(SAMPLE)?.as_slice()), 286);
+        Ok(())
     }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("X4".parse::<Command>(), Err(CommandError::Letter('X')));
+        assert_eq!("".parse::<Command>(), Err(CommandError::Empty));
+        assert!(matches!(
+            "Fx".parse::<Command>(),
+            Err(CommandError::Value(_))
+        ));
+        assert_eq!("B7".parse::<Command>(), Ok(Command::Backward(7)));
+        assert!(generator("F10\nQ3").is_err());
+    }
+
+    #[test]
+    fn trajectory() -> Result<()> {
+        let mut ship = Navigator::new(Mode::Waypoint);
+        ship.run(&generator(SAMPLE)?);
+        let states = ship.trajectory();
+        assert_eq!(states.len(), 6);
+        assert_eq!(states[1].position, Point::new(100, 10));
+        assert_eq!(states[4].waypoint, Point::new(4, -10));
+        assert_eq!(states[5].position, Point::new(214, -72));
+        assert_eq!(ship.trajectory_csv().lines().nth(2), Some("100,10,90,10,1"));
+        Ok(())
+    }
+
+    #[test]
+    fn arbitrary_angles() -> Result<()> {
+        let mut ship = Navigator::new(Mode::Heading);
+        ship.run(&generator("L45\nF10\nB10\nR450\nF2")?);
+        let state = ship.state();
+        assert_eq!(state.heading, 135);
+        assert!((state.position.east() - 2f64.sqrt()).abs() < 1e-9);
+        assert!((state.position.north() + 2f64.sqrt()).abs() < 1e-9);
+
+        let mut ship = Navigator::new(Mode::Waypoint);
+        ship.run(&generator("R30\nL30\nF1")?);
+        let position = ship.state().position;
+        assert!((position.east() - 10.0).abs() < 1e-9);
+        assert!((position.north() - 1.0).abs() < 1e-9);
+        Ok(())
+    }
+
     #[test]
-    fn test_part2() {
-        assert_eq!(solve_part2(generator(SAMPLE).as_slice()), 286);
+    fn exact_quarter_turns() -> Result<()> {
+        // Past 2^53 an f64 can't tell these apart.
+        let far = (1i64 << 53) + 1;
+        let mut ship = Navigator::new(Mode::Waypoint);
+        ship.run(&generator(&format!("R90\nL270\nF{}", far))?);
+        assert_eq!(ship.state().waypoint, Point::new(-10, -1));
+        assert_eq!(ship.state().position, Point::new(-10 * far, -far));
+        assert_eq!(ship.state().position.manhattan(), 11 * far);
+        assert_eq!(Point::heading(-90), Point::new(-1, 0));
+        Ok(())
     }
 }
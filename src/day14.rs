@@ -1,41 +1,142 @@
 use eyre::{eyre, Error, Result};
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, fmt, str::FromStr};
 
 type GeneratorType = Instruction;
 
+const MASK_BITS: usize = 36;
+
+/// A parsed `mask = ..` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mask {
+    ones: u64,
+    zeros: u64,
+    floating: u64,
+}
+
+impl Mask {
+    // Leaves values untouched, what the version 1 decoder starts with.
+    const ALL_FLOATING: Mask = Mask {
+        ones: 0,
+        zeros: 0,
+        floating: (1 << MASK_BITS) - 1,
+    };
+    // Leaves addresses untouched, what the version 2 decoder starts with.
+    const ALL_ZEROS: Mask = Mask {
+        ones: 0,
+        zeros: (1 << MASK_BITS) - 1,
+        floating: 0,
+    };
+}
+
+impl FromStr for Mask {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != MASK_BITS {
+            return Err(eyre!(
+                "Mask {:?} is {} long, expected {}",
+                s,
+                s.len(),
+                MASK_BITS
+            ));
+        }
+        s.chars().try_fold(
+            Mask {
+                ones: 0,
+                zeros: 0,
+                floating: 0,
+            },
+            |mask, c| {
+                let (one, zero, float) = match c {
+                    '1' => (1, 0, 0),
+                    '0' => (0, 1, 0),
+                    'X' => (0, 0, 1),
+                    _ => return Err(eyre!("Unexpected {:?} in mask {:?}", c, s)),
+                };
+                Ok(Mask {
+                    ones: (mask.ones << 1) | one,
+                    zeros: (mask.zeros << 1) | zero,
+                    floating: (mask.floating << 1) | float,
+                })
+            },
+        )
+    }
+}
+
+/// One `mem[..] = ..` as the decoder resolved it, `line` indexing into the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Write {
+    pub line: usize,
+    pub addresses: AddressSet,
+    pub value: u64,
+}
+
+impl fmt::Display for Write {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: mem[{}] = {}",
+            self.line + 1,
+            self.addresses,
+            self.value
+        )
+    }
+}
+
+/// The two ways the docking program reads a mask.
+pub trait Decoder {
+    fn set_mask(&mut self, mask: &Mask);
+    fn write(&mut self, register: u64, value: u64) -> (AddressSet, u64);
+    fn sum(&self) -> u64;
+
+    /// Runs `program`, returning the trace of every write it made.
+    fn run(&mut self, program: &[Instruction]) -> Vec<Write> {
+        program
+            .iter()
+            .enumerate()
+            .filter_map(|(line, instruction)| match instruction {
+                Instruction::Mask(mask) => {
+                    self.set_mask(mask);
+                    None
+                }
+                Instruction::Mem { register, value } => {
+                    let (addresses, value) = self.write(*register as u64, *value);
+                    Some(Write {
+                        line,
+                        addresses,
+                        value,
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// Version 1: the mask rewrites values.
 #[derive(Debug, Clone)]
-struct Memory {
-    registers: HashMap<usize, u64>,
-    ones_mask: u64,
-    zeros_mask: u64,
+pub struct Memory {
+    registers: HashMap<u64, u64>,
+    mask: Mask,
 }
 
-impl Memory {
-    fn new() -> Self {
+impl Default for Memory {
+    fn default() -> Self {
         Memory {
             registers: HashMap::new(),
-            ones_mask: 0,
-            zeros_mask: 1,
+            mask: Mask::ALL_FLOATING,
         }
     }
+}
 
-    fn apply(&mut self, instruction: &Instruction) {
-        match instruction {
-            Instruction::Mask(mask_str) => {
-                self.ones_mask = mask_str.chars().fold(0, |acc, c| match c {
-                    '1' => (acc << 1) | 1,
-                    _ => (acc << 1),
-                });
-                self.zeros_mask = mask_str.chars().fold(0, |acc, c| match c {
-                    '0' => acc << 1,
-                    _ => (acc << 1) | 1,
-                });
-            }
-            Instruction::Mem { register, value } => {
-                let entry = self.registers.entry(*register).or_insert(0);
-                *entry = (value | self.ones_mask) & self.zeros_mask;
-            }
-        };
+impl Decoder for Memory {
+    fn set_mask(&mut self, mask: &Mask) {
+        self.mask = *mask;
+    }
+
+    fn write(&mut self, register: u64, value: u64) -> (AddressSet, u64) {
+        let value = (value | self.mask.ones) & !self.mask.zeros;
+        self.registers.insert(register, value);
+        (AddressSet::new(register, 0), value)
     }
 
     fn sum(&self) -> u64 {
@@ -43,56 +144,115 @@ impl Memory {
     }
 }
 
+/// Every address matching `fixed` on the bits that are not `floating`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressSet {
+    fixed: u64,
+    floating: u64,
+}
+
+impl AddressSet {
+    pub fn new(fixed: u64, floating: u64) -> Self {
+        AddressSet {
+            fixed: fixed & !floating,
+            floating,
+        }
+    }
+
+    /// How many addresses the set covers, never zero.
+    pub fn size(&self) -> u64 {
+        1 << self.floating.count_ones()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        address & !self.floating == self.fixed
+    }
+
+    pub fn intersects(&self, other: &AddressSet) -> bool {
+        let both_fixed = !self.floating & !other.floating;
+        (self.fixed ^ other.fixed) & both_fixed == 0
+    }
+
+    /// Splits `self - other` into disjoint sets, pinning one floating bit per piece.
+    fn subtract(&self, other: &AddressSet) -> Vec<AddressSet> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let mut pin = self.floating & !other.floating;
+        while pin != 0 {
+            let bit = pin & pin.wrapping_neg();
+            pin &= !bit;
+            rest.floating &= !bit;
+            pieces.push(AddressSet::new(
+                rest.fixed | (!other.fixed & bit),
+                rest.floating,
+            ));
+            rest.fixed |= other.fixed & bit;
+        }
+        pieces
+    }
+}
+
+/// The `MASK_BITS` address bits, most significant first, with `X` for floating ones.
+impl fmt::Display for AddressSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bit in (0..MASK_BITS).rev() {
+            let c = if self.floating >> bit & 1 == 1 {
+                'X'
+            } else if self.fixed >> bit & 1 == 1 {
+                '1'
+            } else {
+                '0'
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Version 2: the mask rewrites addresses, floating bits taking both values.
 #[derive(Debug)]
-struct Memory2 {
-    registers: HashMap<usize, u64>,
-    masks: Vec<(usize, usize)>,
+pub struct Memory2 {
+    writes: Vec<(AddressSet, u64)>,
+    mask: Mask,
 }
 
-impl Memory2 {
-    fn new() -> Self {
+impl Default for Memory2 {
+    fn default() -> Self {
         Memory2 {
-            registers: HashMap::new(),
-            masks: Vec::new(),
+            writes: Vec::new(),
+            mask: Mask::ALL_ZEROS,
         }
     }
+}
 
-    fn apply(&mut self, instruction: &Instruction) {
-        match instruction {
-            Instruction::Mask(mask_str) => {
-                self.masks = mask_str.chars().fold(vec![(0, 0)], |acc, c| match c {
-                    '1' => acc
-                        .iter()
-                        .map(|(ones, zeros)| ((ones << 1) | 1, (zeros << 1) | 1))
-                        .collect(),
-                    'X' => acc
-                        .iter()
-                        .map(|(ones, zeros)| ((ones << 1) | 1, (zeros << 1) | 1))
-                        .chain(acc.iter().map(|(ones, zeros)| (ones << 1, zeros << 1)))
-                        .collect(),
-                    _ => acc
-                        .iter()
-                        .map(|(ones, zeros)| ((ones << 1), (zeros << 1) | 1))
-                        .collect(),
-                });
-            }
-            Instruction::Mem { register, value } => {
-                for (ones, zeros) in self.masks.iter() {
-                    let val = self.registers.entry((register | ones) & zeros).or_insert(0);
-                    *val = *value;
-                }
-            }
-        }
+impl Decoder for Memory2 {
+    fn set_mask(&mut self, mask: &Mask) {
+        self.mask = *mask;
+    }
+
+    fn write(&mut self, register: u64, value: u64) -> (AddressSet, u64) {
+        let target = AddressSet::new(register | self.mask.ones, self.mask.floating);
+        self.writes = self
+            .writes
+            .iter()
+            .flat_map(|(set, v)| set.subtract(&target).into_iter().map(move |s| (s, *v)))
+            .collect();
+        self.writes.push((target, value));
+        (target, value)
     }
 
     fn sum(&self) -> u64 {
-        self.registers.values().sum()
+        self.writes.iter().map(|(set, v)| set.size() * v).sum()
     }
 }
 
+/// One line of the initialization program.
 #[derive(Debug, Clone)]
-enum Instruction {
-    Mask(String),
+pub enum Instruction {
+    Mask(Mask),
     Mem { register: usize, value: u64 },
 }
 
@@ -106,7 +266,7 @@ impl FromStr for Instruction {
                 inst.next()
                     .ok_or_else(|| eyre!("No Mask"))?
                     .trim()
-                    .to_string(),
+                    .parse()?,
             ),
             mem => {
                 let (_, register) = mem.split_at(4);
@@ -124,28 +284,24 @@ impl FromStr for Instruction {
 }
 
 #[aoc_generator(day14)]
-fn generator(input: &str) -> Vec<GeneratorType> {
+fn generator(input: &str) -> Result<Vec<GeneratorType>> {
     input
         .split('\n')
-        .map(|l| l.parse::<Instruction>().unwrap())
+        .map(|l| l.parse::<Instruction>())
         .collect()
 }
 
 #[aoc(day14, part1)]
 fn solve_part1(input: &[GeneratorType]) -> u64 {
-    let mut mem = Memory::new();
-    input.iter().for_each(|i| {
-        mem.apply(i);
-    });
+    let mut mem = Memory::default();
+    mem.run(input);
     mem.sum()
 }
 
 #[aoc(day14, part2)]
 fn solve_part2(input: &[GeneratorType]) -> u64 {
-    let mut mem = Memory2::new();
-    input.iter().for_each(|i| {
-        mem.apply(i);
-    });
+    let mut mem = Memory2::default();
+    mem.run(input);
     mem.sum()
 }
 
@@ -160,7 +316,7 @@ mem[8] = 0";
 
     #[test]
     fn test_part1() {
-        assert_eq!(solve_part1(generator(SAMPLE).as_slice()), 165);
+        assert_eq!(solve_part1(generator(SAMPLE).unwrap().as_slice()), 165);
     }
     #[test]
     fn test_part2() {
@@ -168,6 +324,70 @@ mem[8] = 0";
 mem[42] = 100
 mask = 00000000000000000000000000000000X0XX
 mem[26] = 1";
-        assert_eq!(solve_part2(generator(sample).as_slice()), 208);
+        assert_eq!(solve_part2(generator(sample).unwrap().as_slice()), 208);
+    }
+
+    #[test]
+    fn test_part2_wide_floating() {
+        let sample = "mask = 000000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 1
+mask = 0000001XXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 2
+mask = 000000110000000000000000000000000000
+mem[0] = 5";
+        assert_eq!(
+            solve_part2(generator(sample).unwrap().as_slice()),
+            (1 << 29) + 2 * ((1 << 29) - 1) + 5
+        );
+    }
+
+    #[test]
+    fn test_mask_validation() {
+        assert!("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X"
+            .parse::<Mask>()
+            .is_ok());
+        assert!("XXXX1XXXX0X".parse::<Mask>().is_err());
+        assert!("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX2X"
+            .parse::<Mask>()
+            .is_err());
+        assert!(generator("mask = 1X0\nmem[8] = 11").is_err());
+    }
+
+    #[test]
+    fn test_no_initial_mask() {
+        let program = generator("mem[8] = 11\nmem[7] = 101").unwrap();
+        assert_eq!(solve_part1(&program), 112);
+        assert_eq!(solve_part2(&program), 112);
+    }
+
+    #[test]
+    fn test_trace() {
+        let program = generator(SAMPLE).unwrap();
+        let trace = Memory::default().run(&program);
+        assert_eq!(
+            trace.iter().map(|w| (w.line, w.value)).collect::<Vec<_>>(),
+            vec![(1, 73), (2, 101), (3, 64)]
+        );
+        assert_eq!(
+            trace[2].to_string(),
+            "line 4: mem[000000000000000000000000000000001000] = 64"
+        );
+        let trace = Memory2::default().run(&program);
+        assert_eq!(trace[0].addresses.size(), 1 << 34);
+        assert!(trace[0].addresses.contains(1 << 35 | 1 << 6));
+        assert!(!trace[0].addresses.contains(1 << 6 | 1 << 1));
+        assert_eq!(
+            trace[0].addresses.to_string(),
+            "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X"
+        );
+    }
+
+    #[test]
+    fn test_subtract() {
+        let a = AddressSet::new(0, 0b111);
+        let b = AddressSet::new(0b010, 0b001);
+        let pieces = a.subtract(&b);
+        assert_eq!(pieces.iter().map(AddressSet::size).sum::<u64>(), 6);
+        assert!(pieces.iter().all(|p| !p.intersects(&b)));
     }
 }
@@ -1,6 +1,10 @@
+use crate::{
+    escape::{csv_escape, json_escape},
+    matching::{self, Matcher},
+};
 use eyre::{Error, Result, WrapErr};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     str::FromStr,
 };
 
@@ -24,12 +28,6 @@ impl FromStr for Ticket {
 }
 
 impl Ticket {
-    fn invalid_fields<'a>(&'a self, fields: &'a [Field]) -> impl Iterator<Item = &'a u64> {
-        self.field_values
-            .iter()
-            .filter(move |v| !fields.iter().any(|f| f.valid(v)))
-    }
-
     fn is_valid(&self, fields: &[Field]) -> bool {
         self.field_values
             .iter()
@@ -75,20 +73,141 @@ impl FromStr for Field {
     }
 }
 
-struct PuzzleInput {
+pub struct PuzzleInput {
     fields: Vec<Field>,
     my_ticket: Ticket,
     tickets: Vec<Ticket>,
 }
 
-impl PuzzleInput {
-    fn invalid_error_rate(&self) -> u64 {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueReport {
+    pub column: usize,
+    pub value: u64,
+    pub fields: Vec<String>,
+}
+
+impl ValueReport {
+    pub fn is_valid(&self) -> bool {
+        !self.fields.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketReport {
+    pub ticket: usize,
+    pub values: Vec<ValueReport>,
+}
+
+impl TicketReport {
+    pub fn invalid_values(&self) -> impl Iterator<Item = &ValueReport> {
+        self.values.iter().filter(|v| !v.is_valid())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.invalid_values().next().is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub tickets: Vec<TicketReport>,
+}
+
+impl ValidationReport {
+    pub fn error_rate(&self) -> u64 {
         self.tickets
             .iter()
-            .flat_map(|ticket| ticket.invalid_fields(self.fields.as_slice()))
+            .flat_map(|t| t.invalid_values())
+            .map(|v| v.value)
             .sum()
     }
 
+    // One row per value, the matching field names joined with ';'.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("ticket,column,value,valid,fields\n");
+        for t in self.tickets.iter() {
+            for v in t.values.iter() {
+                out.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    t.ticket,
+                    v.column,
+                    v.value,
+                    v.is_valid(),
+                    csv_escape(&v.fields.join(";"))
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let tickets: Vec<_> = self
+            .tickets
+            .iter()
+            .map(|t| {
+                let values: Vec<_> = t
+                    .values
+                    .iter()
+                    .map(|v| {
+                        let fields: Vec<_> = v
+                            .fields
+                            .iter()
+                            .map(|f| format!("\"{}\"", json_escape(f)))
+                            .collect();
+                        format!(
+                            "{{\"column\":{},\"value\":{},\"valid\":{},\"fields\":[{}]}}",
+                            v.column,
+                            v.value,
+                            v.is_valid(),
+                            fields.join(",")
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"ticket\":{},\"valid\":{},\"values\":[{}]}}",
+                    t.ticket,
+                    t.is_valid(),
+                    values.join(",")
+                )
+            })
+            .collect();
+        format!("{{\"tickets\":[{}]}}", tickets.join(","))
+    }
+}
+
+impl PuzzleInput {
+    pub fn validation_report(&self) -> ValidationReport {
+        ValidationReport {
+            tickets: self
+                .tickets
+                .iter()
+                .enumerate()
+                .map(|(ticket, t)| TicketReport {
+                    ticket,
+                    values: t
+                        .field_values
+                        .iter()
+                        .enumerate()
+                        .map(|(column, value)| ValueReport {
+                            column,
+                            value: *value,
+                            fields: self
+                                .fields
+                                .iter()
+                                .filter(|f| f.valid(value))
+                                .map(|f| f.name.clone())
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn invalid_error_rate(&self) -> u64 {
+        self.validation_report().error_rate()
+    }
+
     fn valid_tickets(&self) -> Vec<&Ticket> {
         self.tickets
             .iter()
@@ -140,46 +259,75 @@ fn solve_part1(input: &GeneratorType) -> u64 {
     input.invalid_error_rate()
 }
 
-#[aoc(day16, part2)]
-fn solve_part2(input: &GeneratorType) -> u64 {
-    let tickets = input.valid_tickets();
-    let mut map: HashMap<String, HashSet<usize>> = HashMap::new();
-    for f in input.fields.iter() {
-        for t in tickets.iter() {
-            let mut ticket_set = HashSet::new();
-            for (i, v) in t.field_values.iter().enumerate() {
-                if f.valid(&v) {
-                    ticket_set.insert(i);
-                };
+pub type Ambiguity = matching::Ambiguity<String, usize>;
+pub type PlacementError = matching::PlacementError<String, usize>;
+
+/// Places each field in a column, with `columns` in all.
+fn place_fields(
+    candidates: BTreeMap<String, BTreeSet<usize>>,
+    columns: usize,
+) -> Result<HashMap<String, usize>, PlacementError> {
+    // A column only one field fits must be that field's, but only when every column gets used.
+    let hidden_single = |m: &Matcher<String, usize>| {
+        let open = m.open();
+        if open.len() != columns - m.assigned().len() {
+            return None;
+        }
+        let mut holders: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for f in open.iter() {
+            for c in m.candidates(f).iter() {
+                holders.entry(*c).or_default().push(f.clone());
             }
-            let m2 = map
-                .entry(f.name.clone())
-                .or_insert_with(|| ticket_set.clone());
-            *m2 = ticket_set.intersection(m2).cloned().collect();
         }
-    }
-
-    let mut done: HashMap<String, usize> = HashMap::new();
+        holders
+            .into_iter()
+            .find(|(_, fields)| fields.len() == 1)
+            .map(|(c, mut fields)| (fields.remove(0), c))
+    };
+    let placement = Matcher::new(candidates).solve(hidden_single, |_, _| {});
+    Ok(placement.result()?.clone().into_iter().collect())
+}
 
-    while !map.is_empty() {
-        let (r_k, r_v) = if let Some((r_k, r_v)) = map.iter().find(|(_, v)| v.len() == 1) {
-            let r_v = r_v.iter().last().unwrap();
-            (r_k.clone(), *r_v)
-        } else {
-            panic!("WTF");
-        };
+impl PuzzleInput {
+    pub fn assign_fields(&self) -> Result<HashMap<String, usize>, PlacementError> {
+        let tickets = self.valid_tickets();
+        let columns = self.my_ticket.field_values.len();
+        let candidates = self
+            .fields
+            .iter()
+            .map(|f| {
+                let fits = (0..columns)
+                    .filter(|c| {
+                        tickets
+                            .iter()
+                            .all(|t| t.field_values.get(*c).is_some_and(|v| f.valid(v)))
+                    })
+                    .collect();
+                (f.name.clone(), fits)
+            })
+            .collect();
+        place_fields(candidates, columns)
+    }
+}
 
-        map.remove(r_k.as_str());
-        for (_, v) in map.iter_mut() {
-            v.remove(&r_v);
-        }
-        done.insert(r_k, r_v);
+impl PuzzleInput {
+    pub fn decoded_ticket(&self) -> Result<BTreeMap<String, u64>, PlacementError> {
+        Ok(self
+            .assign_fields()?
+            .into_iter()
+            .map(|(name, column)| (name, self.my_ticket.field_values[column]))
+            .collect())
     }
+}
 
-    done.iter()
+#[aoc(day16, part2)]
+fn solve_part2(input: &GeneratorType) -> Result<u64> {
+    Ok(input
+        .decoded_ticket()?
+        .iter()
         .filter(|(k, _)| k.starts_with("departure"))
-        .map(|(_, v)| input.my_ticket.field_values.get(*v).unwrap())
-        .product()
+        .map(|(_, v)| v)
+        .product())
 }
 
 #[cfg(test)]
@@ -203,8 +351,124 @@ nearby tickets:
     fn test_part1() {
         assert_eq!(solve_part1(&generator(SAMPLE).unwrap()), 71);
     }
-    //#[test]
+    const SAMPLE_2: &str = "class: 0-1 or 4-19
+row: 0-5 or 8-19
+seat: 0-13 or 16-19
+
+your ticket:
+11,12,13
+
+nearby tickets:
+3,9,18
+15,1,5
+5,14,9";
+
+    #[test]
     fn test_part2() {
-        assert_eq!(solve_part2(&generator(SAMPLE).unwrap()), 0);
+        let input = generator(SAMPLE_2).unwrap();
+        let fields = input.assign_fields().unwrap();
+        assert_eq!(fields["row"], 0);
+        assert_eq!(fields["class"], 1);
+        assert_eq!(fields["seat"], 2);
+        assert_eq!(solve_part2(&input).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_decoded_ticket() {
+        let ticket = generator(SAMPLE_2).unwrap().decoded_ticket().unwrap();
+        assert_eq!(
+            ticket.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("class".to_string(), 12),
+                ("row".to_string(), 11),
+                ("seat".to_string(), 13)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let report = generator(SAMPLE).unwrap().validation_report();
+        assert_eq!(report.tickets.len(), 4);
+        assert_eq!(report.error_rate(), 71);
+        let invalid: Vec<_> = report
+            .tickets
+            .iter()
+            .map(|t| t.invalid_values().map(|v| v.value).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(invalid, vec![vec![], vec![4], vec![55], vec![12]]);
+        assert_eq!(report.tickets[0].values[2].fields, vec!["seat"]);
+
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 13);
+        assert_eq!(csv.lines().nth(3), Some("0,2,47,true,seat"));
+        assert_eq!(csv.lines().nth(5), Some("1,1,4,false,"));
+
+        let json = report.to_json();
+        assert!(json.starts_with(
+            "{\"tickets\":[{\"ticket\":0,\"valid\":true,\"values\":[{\"column\":0,\"value\":7,\"valid\":true,\"fields\":[\"class\",\"row\"]}"
+        ));
+    }
+
+    fn solve(sets: &[&[usize]], columns: usize) -> Result<HashMap<String, usize>, PlacementError> {
+        let candidates = sets
+            .iter()
+            .enumerate()
+            .map(|(f, s)| {
+                (
+                    ((b'a' + f as u8) as char).to_string(),
+                    s.iter().cloned().collect(),
+                )
+            })
+            .collect();
+        place_fields(candidates, columns)
+    }
+
+    fn ambiguous(solved: Result<HashMap<String, usize>, PlacementError>) -> Ambiguity {
+        match solved {
+            Err(PlacementError::Ambiguous(ambiguity)) => ambiguity,
+            other => panic!("expected an ambiguity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hidden_single() {
+        // No field has a single candidate, but column 2 only fits "c".
+        let ambiguity = ambiguous(solve(&[&[0, 1], &[0, 1], &[0, 1, 2]], 3));
+        assert_eq!(ambiguity.assigned.get("c"), Some(&2));
+        assert_eq!(ambiguity.unresolved.len(), 2);
+    }
+
+    #[test]
+    fn test_matching_fallback() {
+        // Propagation stalls, matching shows "a" and "b" must share columns 0 and 1.
+        let ambiguity = ambiguous(solve(&[&[0, 1, 2], &[0, 1, 2], &[2, 3], &[2, 3]], 4));
+        assert_eq!(ambiguity.unresolved["a"], [0, 1].iter().cloned().collect());
+        assert_eq!(ambiguity.unresolved["c"], [2, 3].iter().cloned().collect());
+
+        // "d" is a naked single, after which only "c" can take column 2.
+        let solved = solve(&[&[0, 1], &[0, 1], &[0, 1, 2, 3], &[3]], 4);
+        assert_eq!(ambiguous(solved).assigned["c"], 2);
+    }
+
+    #[test]
+    fn test_spare_columns() {
+        // Columns 1 and 2 both fit only "b", so neither is forced.
+        let ambiguity = ambiguous(solve(&[&[0], &[1, 2]], 3));
+        assert_eq!(ambiguity.assigned["a"], 0);
+        assert_eq!(ambiguity.unresolved["b"], [1, 2].iter().cloned().collect());
+    }
+
+    #[test]
+    fn test_impossible() {
+        let err = solve(&[&[0], &[0], &[1, 2]], 3).unwrap_err();
+        assert_eq!(
+            err,
+            PlacementError::Infeasible(std::iter::once("b".to_string()).collect())
+        );
+        assert_eq!(
+            err.to_string(),
+            "No assignment exists: nothing is left for b"
+        );
     }
 }
@@ -1,11 +1,10 @@
-use eyre::Result;
-use itertools::iproduct;
-use std::collections::{HashMap, HashSet};
+use eyre::{eyre, Error, Result};
+use std::{collections::HashSet, str::FromStr};
 
-type Point = (isize, isize, isize);
-type Point4 = (isize, isize, isize, isize);
+/// How many cycles both parts run for.
+pub const CYCLES: usize = 6;
 
-type GeneratorType = HashSet<Point>;
+type GeneratorType = Vec<[i32; 2]>;
 
 #[aoc_generator(day17)]
 fn generator(input: &str) -> Result<GeneratorType> {
@@ -15,7 +14,7 @@ fn generator(input: &str) -> Result<GeneratorType> {
         .flat_map(|(x, line)| {
             line.chars().enumerate().filter_map(move |(y, val)| {
                 if val == '#' {
-                    Some((x as isize, y as isize, 0))
+                    Some([x as i32, y as i32])
                 } else {
                     None
                 }
@@ -24,74 +23,331 @@ fn generator(input: &str) -> Result<GeneratorType> {
         .collect())
 }
 
-fn neigbors(cell: &Point) -> HashSet<Point> {
-    let (x, y, z) = cell;
-    iproduct!(-1..=1, -1..=1, -1..=1)
-        .map(|(d_x, d_y, d_z)| (x + d_x, y + d_y, z + d_z))
-        .filter(|n| n != cell)
-        .collect()
+#[aoc(day17, part1)]
+fn solve_part1(input: &GeneratorType) -> usize {
+    *simulate::<3>(input, &Rules::default(), CYCLES)
+        .last()
+        .unwrap()
 }
 
-fn neigbors4(cell: &Point4) -> HashSet<Point4> {
-    let (x, y, z, w) = cell;
-    iproduct!(-1..=1, -1..=1, -1..=1, -1..=1)
-        .map(|(d_x, d_y, d_z, d_w)| (x + d_x, y + d_y, z + d_z, w + d_w))
-        .filter(|n| n != cell)
-        .collect()
+#[aoc(day17, part2)]
+fn solve_part2(input: &GeneratorType) -> usize {
+    *simulate::<4>(input, &Rules::default(), CYCLES)
+        .last()
+        .unwrap()
 }
 
-#[aoc(day17, part1)]
-fn solve_part1(input: &GeneratorType) -> usize {
-    let mut last = input.clone();
-    for _ in 0..6 {
-        let mut consider: HashMap<Point, usize> = HashMap::new();
-        for x in last.iter().flat_map(|x| neigbors(x)) {
-            let point_count = consider.entry(x).or_insert(0);
-            *point_count += 1;
+/// Neighbour counts that switch a cube on, or keep it on, as in `B3/S23`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    pub birth: Vec<usize>,
+    pub survival: Vec<usize>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+}
+
+impl FromStr for Rules {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = |part: &str, prefix: char| -> Result<Vec<usize>> {
+            let digits = part
+                .strip_prefix(prefix)
+                .ok_or_else(|| eyre!("{:?} should start with {}", part, prefix))?;
+            digits
+                .chars()
+                .map(|c| {
+                    c.to_digit(10)
+                        .map(|d| d as usize)
+                        .ok_or_else(|| eyre!("{:?} is not a neighbour count", c))
+                })
+                .collect()
+        };
+        let mut parts = s.split('/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(birth), Some(survival), None) => Ok(Rules {
+                birth: counts(birth, 'B')?,
+                survival: counts(survival, 'S')?,
+            }),
+            _ => Err(eyre!("{:?} should look like B3/S23", s)),
         }
+    }
+}
 
-        let mut next: HashSet<Point> = HashSet::new();
-        for (possible, on_neighbors) in consider.into_iter() {
-            if (last.contains(&possible) && (on_neighbors == 2 || on_neighbors == 3))
-                || (!last.contains(&possible) && on_neighbors == 3)
-            {
-                next.insert(possible);
-            };
+impl Rules {
+    pub fn next(&self, active: bool, neighbours: usize) -> bool {
+        if active {
+            self.survival.contains(&neighbours)
+        } else {
+            self.birth.contains(&neighbours)
         }
-        last = next;
     }
+}
+
+/// Every offset to a neighbouring cell, the cell itself left out.
+fn offsets<const N: usize>() -> Vec<[i32; N]> {
+    (0..3usize.pow(N as u32))
+        .map(|mut i| {
+            let mut offset = [0; N];
+            for d in offset.iter_mut() {
+                *d = (i % 3) as i32 - 1;
+                i /= 3;
+            }
+            offset
+        })
+        .filter(|offset| offset.iter().any(|d| *d != 0))
+        .collect()
+}
 
-    last.iter().count()
+/// The start is a flat slice, so mirroring any axis past the first two, or
+/// swapping two of them, maps the pocket onto itself. Cells are stored with
+/// those coordinates made positive and sorted.
+fn canonical<const N: usize>(mut cell: [i32; N]) -> [i32; N] {
+    for c in cell[2..].iter_mut() {
+        *c = c.abs();
+    }
+    cell[2..].sort_unstable();
+    cell
 }
 
-#[aoc(day17, part2)]
-fn solve_part2(input: &GeneratorType) -> usize {
-    let mut last = input
-        .iter()
-        .map(|p| {
-            let (x, y, z) = p;
-            (*x, *y, *z, 0)
+/// How many real cells a canonical one stands for.
+fn multiplicity<const N: usize>(cell: &[i32; N]) -> usize {
+    let extra = &cell[2..];
+    let factorial = |n: usize| (1..=n).product::<usize>();
+    let signs = 1 << extra.iter().filter(|c| **c != 0).count();
+    let mut orders = factorial(extra.len());
+    let mut start = 0;
+    while start < extra.len() {
+        let run = extra[start..]
+            .iter()
+            .take_while(|c| **c == extra[start])
+            .count();
+        orders /= factorial(run);
+        start += run;
+    }
+    signs * orders
+}
+
+/// Active cubes in `N` dimensions, only one per mirror image.
+#[derive(Debug, Clone)]
+pub struct Pocket<const N: usize> {
+    rules: Rules,
+    active: HashSet<[i32; N]>,
+    offsets: Vec<[i32; N]>,
+}
+
+impl<const N: usize> Pocket<N> {
+    /// `slice` cells take the first two coordinates, the rest are 0.
+    pub fn new(slice: &[[i32; 2]], rules: Rules) -> Self {
+        assert!(N >= 2, "The starting slice needs two dimensions");
+        Pocket {
+            rules,
+            active: slice
+                .iter()
+                .map(|[x, y]| {
+                    let mut cell = [0; N];
+                    cell[0] = *x;
+                    cell[1] = *y;
+                    cell
+                })
+                .collect(),
+            offsets: offsets(),
+        }
+    }
+
+    fn neighbours(&self, cell: [i32; N]) -> impl Iterator<Item = [i32; N]> + '_ {
+        self.offsets.iter().map(move |offset| {
+            let mut next = cell;
+            for (c, d) in next.iter_mut().zip(offset.iter()) {
+                *c += d;
+            }
+            canonical(next)
         })
-        .collect::<HashSet<Point4>>();
-    for _ in 0..6 {
-        let mut consider: HashMap<Point4, usize> = HashMap::new();
-        for x in last.iter().flat_map(|x| neigbors4(x)) {
-            let point_count = consider.entry(x).or_insert(0);
-            *point_count += 1;
+    }
+
+    pub fn step(&mut self) {
+        let candidates: HashSet<_> = self
+            .active
+            .iter()
+            .flat_map(|cell| self.neighbours(*cell))
+            .chain(self.active.iter().cloned())
+            .collect();
+        self.active = candidates
+            .into_iter()
+            .filter(|cell| {
+                let on = self
+                    .neighbours(*cell)
+                    .filter(|n| self.active.contains(n))
+                    .count();
+                self.rules.next(self.active.contains(cell), on)
+            })
+            .collect();
+    }
+
+    pub fn population(&self) -> usize {
+        self.active.iter().map(multiplicity).sum()
+    }
+}
+
+/// What both the sparse `Pocket` and the `Dense` grid can do.
+pub trait Backend {
+    /// `slice` cells take the first two coordinates, the rest are 0.
+    fn from_slice(slice: &[[i32; 2]], rules: Rules) -> Self;
+    fn step(&mut self);
+    fn population(&self) -> usize;
+}
+
+impl<const N: usize> Backend for Pocket<N> {
+    fn from_slice(slice: &[[i32; 2]], rules: Rules) -> Self {
+        Pocket::new(slice, rules)
+    }
+
+    fn step(&mut self) {
+        Pocket::step(self)
+    }
+
+    fn population(&self) -> usize {
+        Pocket::population(self)
+    }
+}
+
+/// Every cube in a bounding box, one bit each, growing by one on every side per cycle.
+#[derive(Debug, Clone)]
+pub struct Dense<const N: usize> {
+    rules: Rules,
+    min: [i32; N],
+    dims: [usize; N],
+    bits: Vec<u64>,
+}
+
+/// Index steps per axis, the last axis being contiguous.
+fn strides<const N: usize>(dims: &[usize; N]) -> [usize; N] {
+    let mut strides = [1; N];
+    for axis in (0..N.saturating_sub(1)).rev() {
+        strides[axis] = strides[axis + 1] * dims[axis + 1];
+    }
+    strides
+}
+
+impl<const N: usize> Dense<N> {
+    fn len(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    fn get(&self, index: usize) -> bool {
+        self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn contains(&self, cell: &[i32; N]) -> bool {
+        let strides = strides(&self.dims);
+        let mut index = 0;
+        for axis in 0..N {
+            let offset = cell[axis] - self.min[axis];
+            if offset < 0 || offset as usize >= self.dims[axis] {
+                return false;
+            }
+            index += offset as usize * strides[axis];
         }
+        self.get(index)
+    }
+}
 
-        let mut next: HashSet<Point4> = HashSet::new();
-        for (possible, on_neighbors) in consider.into_iter() {
-            if (last.contains(&possible) && (on_neighbors == 2 || on_neighbors == 3))
-                || (!last.contains(&possible) && on_neighbors == 3)
-            {
-                next.insert(possible);
-            };
+impl<const N: usize> Backend for Dense<N> {
+    fn from_slice(slice: &[[i32; 2]], rules: Rules) -> Self {
+        assert!(N >= 2, "The starting slice needs two dimensions");
+        let mut min = [0; N];
+        let mut dims = [1; N];
+        for axis in 0..2 {
+            min[axis] = slice.iter().map(|cell| cell[axis]).min().unwrap_or(0);
+            let max = slice.iter().map(|cell| cell[axis]).max().unwrap_or(0);
+            dims[axis] = (max - min[axis] + 1) as usize;
+        }
+        let strides = strides(&dims);
+        let mut bits = vec![0; dims.iter().product::<usize>().div_ceil(64)];
+        for cell in slice {
+            let index = (0..2)
+                .map(|axis| (cell[axis] - min[axis]) as usize * strides[axis])
+                .sum::<usize>();
+            bits[index / 64] |= 1 << (index % 64);
+        }
+        Dense {
+            rules,
+            min,
+            dims,
+            bits,
+        }
+    }
+
+    fn step(&mut self) {
+        let mut dims = self.dims;
+        for d in dims.iter_mut() {
+            *d += 2;
+        }
+        let (old, new) = (strides(&self.dims), strides(&dims));
+        let len = dims.iter().product();
+
+        // Copy the cubes into the bigger box, one cell in from every side.
+        let mut active = vec![0u16; len];
+        for index in (0..self.len()).filter(|i| self.get(*i)) {
+            let moved: usize = (0..N)
+                .map(|axis| ((index / old[axis]) % self.dims[axis] + 1) * new[axis])
+                .sum();
+            active[moved] = 1;
+        }
+
+        // The sum over each 3x..x3 block, a 1-1-1 box filter along every axis in turn.
+        let mut sums = active.clone();
+        for axis in 0..N {
+            let (stride, dim) = (new[axis], dims[axis]);
+            let last = sums.clone();
+            for (index, sum) in sums.iter_mut().enumerate() {
+                let at = (index / stride) % dim;
+                if at > 0 {
+                    *sum += last[index - stride];
+                }
+                if at + 1 < dim {
+                    *sum += last[index + stride];
+                }
+            }
+        }
+
+        let mut bits = vec![0; len.div_ceil(64)];
+        for index in 0..len {
+            let on = active[index] == 1;
+            if self.rules.next(on, (sums[index] - active[index]) as usize) {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+        for m in self.min.iter_mut() {
+            *m -= 1;
         }
-        last = next;
+        self.dims = dims;
+        self.bits = bits;
     }
 
-    last.iter().count()
+    fn population(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+}
+
+/// The population after each of `cycles` cycles, on the sparse backend.
+pub fn simulate<const N: usize>(slice: &[[i32; 2]], rules: &Rules, cycles: usize) -> Vec<usize> {
+    simulate_with::<Pocket<N>>(slice, rules, cycles)
+}
+
+/// The population after each of `cycles` cycles, on any backend.
+pub fn simulate_with<B: Backend>(slice: &[[i32; 2]], rules: &Rules, cycles: usize) -> Vec<usize> {
+    let mut pocket = B::from_slice(slice, rules.clone());
+    (0..cycles)
+        .map(|_| {
+            pocket.step();
+            pocket.population()
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -104,17 +360,79 @@ mod test {
 
     #[test]
     fn test_neighbors() {
-        let mut s = HashSet::new();
-        s.insert((0, 0, 0));
-        assert_eq!(neigbors(&(0, 0, 0)).iter().count(), 26);
+        assert_eq!(offsets::<3>().len(), 26);
+        assert_eq!(offsets::<4>().len(), 80);
     }
 
     #[test]
     fn test_part1() {
         assert_eq!(solve_part1(&generator(SAMPLE).unwrap()), 112);
     }
-    //    #[test]
+    #[test]
     fn test_part2() {
         assert_eq!(solve_part2(&generator(SAMPLE).unwrap()), 848);
     }
+
+    #[test]
+    fn per_cycle() {
+        let slice = generator(SAMPLE).unwrap();
+        assert_eq!(
+            simulate::<3>(&slice, &Rules::default(), 3),
+            vec![11, 21, 38]
+        );
+        assert_eq!(simulate::<4>(&slice, &Rules::default(), 1), vec![29]);
+        // Plain Life on the glider keeps five cells.
+        assert_eq!(simulate::<2>(&slice, &Rules::default(), 8), vec![5; 8]);
+    }
+
+    #[test]
+    fn symmetry() {
+        assert_eq!(canonical([1, 2, -3, 0, 2]), [1, 2, 0, 2, 3]);
+        assert_eq!(multiplicity(&[0, 0, 0, 0]), 1);
+        assert_eq!(multiplicity(&[0, 0, 0, 1]), 4);
+        assert_eq!(multiplicity(&[0, 0, 1, 1]), 4);
+        assert_eq!(multiplicity(&[0, 0, 1, 2]), 8);
+        assert_eq!(multiplicity(&[0, 0, 1, 2, 2]), 24);
+    }
+
+    #[test]
+    fn other_rules() -> Result<()> {
+        let rules: Rules = "B36/S23".parse()?;
+        assert_eq!(rules.birth, vec![3, 6]);
+        assert!("S23".parse::<Rules>().is_err());
+        assert!("B3/Sx".parse::<Rules>().is_err());
+        // Nothing survives, so only births are left after one cycle.
+        let seeds: Rules = "B2/S".parse()?;
+        let slice = vec![[0, 0], [0, 1]];
+        assert_eq!(simulate::<2>(&slice, &seeds, 2), vec![4, 6]);
+        Ok(())
+    }
+
+    fn backends_agree<const N: usize>(slice: &[[i32; 2]]) {
+        let rules = Rules::default();
+        assert_eq!(
+            simulate_with::<Pocket<N>>(slice, &rules, CYCLES),
+            simulate_with::<Dense<N>>(slice, &rules, CYCLES),
+            "{} dimensions",
+            N
+        );
+    }
+
+    #[test]
+    fn dense_backend() -> Result<()> {
+        let sample = generator(SAMPLE)?;
+        let real = generator(include_str!("../input/2020/day17.txt").trim_end())?;
+        for slice in &[sample, real] {
+            backends_agree::<2>(slice);
+            backends_agree::<3>(slice);
+            backends_agree::<4>(slice);
+        }
+
+        let mut dense = Dense::<3>::from_slice(&generator(SAMPLE)?, Rules::default());
+        dense.step();
+        assert!(dense.contains(&[1, 0, -1]));
+        assert!(!dense.contains(&[0, 1, -1]));
+        assert!(!dense.contains(&[100, 0, 0]));
+        Ok(())
+    }
 }
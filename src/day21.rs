@@ -1,14 +1,19 @@
-use eyre::{eyre, Error};
+use crate::{
+    escape::json_escape,
+    matching::{self, Matcher, Placement, Step},
+};
+use eyre::{eyre, Error, Result};
 use itertools::Itertools;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt,
     str::FromStr,
 };
 
 type GeneratorType = Label;
 
 #[derive(Debug)]
-struct Label {
+pub struct Label {
     ingridients: HashSet<String>,
     allergens: HashSet<String>,
 }
@@ -43,161 +48,279 @@ impl FromStr for Label {
 }
 
 #[aoc_generator(day21)]
-fn generator(input: &str) -> Vec<GeneratorType> {
+fn generator(input: &str) -> Result<Vec<GeneratorType>> {
     input
         .split('\n')
-        .map(|s| s.parse::<Label>().unwrap())
+        .enumerate()
+        .map(|(idx, s)| {
+            s.parse::<Label>()
+                .map_err(|e| eyre!("line {}: {}", idx + 1, e))
+        })
         .collect()
 }
 
-#[aoc(day21, part1)]
-fn solve_part1(input: &[GeneratorType]) -> usize {
-    let mut map: HashMap<&str, Vec<&HashSet<String>>> = HashMap::new();
-    let mut ingridents = HashSet::new();
-    for label in input {
-        for allergen in label.allergens.iter() {
-            map.entry(allergen)
-                .or_insert(Vec::new())
-                .push(&label.ingridients);
-            for ing in label.ingridients.iter() {
-                ingridents.insert(ing.as_str());
-            }
-        }
-    }
+pub type Ambiguity = matching::Ambiguity<String, String>;
+pub type PlacementError = matching::PlacementError<String, String>;
 
-    let mut possible: HashMap<&str, HashSet<&str>> = HashMap::new();
-    for (allergen, ingredients) in map.iter() {
-        possible.insert(
-            allergen,
-            ingredients
-                .iter()
-                .cloned()
-                .flat_map(|a| a.iter().map(|s| s.as_str()))
-                .collect(),
-        );
-    }
+/// One step of the solver's reasoning, in the order it was taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deduction {
+    /// The ingredients shared by every label (numbered from 1) naming the allergen.
+    Candidates {
+        allergen: String,
+        ingredients: BTreeSet<String>,
+        labels: Vec<usize>,
+    },
+    /// Only one candidate was left; `eliminated` names the allergen that claimed each other one.
+    Assigned {
+        allergen: String,
+        ingredient: String,
+        eliminated: BTreeMap<String, String>,
+    },
+    /// No complete assignment gives the allergen this ingredient.
+    RuledOut {
+        allergen: String,
+        ingredient: String,
+    },
+}
 
-    let mut impossible: HashMap<&str, HashSet<&str>> = HashMap::new();
-    for (allergen, ingredients) in map.iter() {
-        impossible.insert(
-            allergen,
-            ingredients
-                .iter()
-                .cartesian_product(ingredients.iter())
-                .flat_map(|(a, b)| HashSet::symmetric_difference(a, b).map(|s| s.as_str()))
-                .collect(),
-        );
-    }
+fn braced(items: &BTreeSet<String>) -> String {
+    format!("{{{}}}", items.iter().join(", "))
+}
 
-    let mut not_safe = HashSet::new();
-    for allergen in map.keys() {
-        let maybe = possible
-            .get(allergen)
-            .unwrap()
-            .difference(impossible.get(allergen).unwrap());
-        for m in maybe {
-            not_safe.insert(*m);
+impl fmt::Display for Deduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deduction::Candidates {
+                allergen,
+                ingredients,
+                labels,
+            } => {
+                let labels = match labels.split_last() {
+                    Some((last, [])) => format!("label {}", last),
+                    Some((last, rest)) => format!("labels {} and {}", rest.iter().join(", "), last),
+                    None => "no labels".to_string(),
+                };
+                write!(
+                    f,
+                    "{} ∈ {} because of {}",
+                    allergen,
+                    braced(ingredients),
+                    labels
+                )
+            }
+            Deduction::Assigned {
+                allergen,
+                ingredient,
+                eliminated,
+            } => {
+                write!(f, "{} = {}", allergen, ingredient)?;
+                if !eliminated.is_empty() {
+                    let claimed = eliminated
+                        .iter()
+                        .map(|(i, by)| format!("{} ({})", i, by))
+                        .join(", ");
+                    write!(f, " after eliminating {}", claimed)?;
+                }
+                Ok(())
+            }
+            Deduction::RuledOut {
+                allergen,
+                ingredient,
+            } => write!(
+                f,
+                "{} ≠ {} because the other allergens could not all be placed",
+                allergen, ingredient
+            ),
         }
     }
+}
 
-    let safe = ingridents.difference(&not_safe).collect::<HashSet<_>>();
+impl Deduction {
+    fn to_json(&self) -> String {
+        let string = |s: &str| format!("\"{}\"", json_escape(s));
+        let list = |items: &BTreeSet<String>| items.iter().map(|i| string(i)).join(",");
+        match self {
+            Deduction::Candidates {
+                allergen,
+                ingredients,
+                labels,
+            } => format!(
+                "{{\"kind\":\"candidates\",\"allergen\":{},\"ingredients\":[{}],\"labels\":[{}]}}",
+                string(allergen),
+                list(ingredients),
+                labels.iter().join(",")
+            ),
+            Deduction::Assigned {
+                allergen,
+                ingredient,
+                eliminated,
+            } => format!(
+                "{{\"kind\":\"assigned\",\"allergen\":{},\"ingredient\":{},\"eliminated\":{{{}}}}}",
+                string(allergen),
+                string(ingredient),
+                eliminated
+                    .iter()
+                    .map(|(i, by)| format!("{}:{}", string(i), string(by)))
+                    .join(",")
+            ),
+            Deduction::RuledOut {
+                allergen,
+                ingredient,
+            } => format!(
+                "{{\"kind\":\"ruled_out\",\"allergen\":{},\"ingredient\":{}}}",
+                string(allergen),
+                string(ingredient)
+            ),
+        }
+    }
+}
 
-    input
-        .iter()
-        .map(|l| {
-            l.ingridients
-                .iter()
-                .filter(|i| safe.contains(&i.as_str()))
-                .count()
-        })
-        .sum()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    /// Ingredients no label lets hold any allergen.
+    pub safe: BTreeSet<String>,
+    /// Which ingredient holds each allergen, and what the rest could still be.
+    pub placement: Placement<String, String>,
+    pub steps: Vec<Deduction>,
 }
 
-#[aoc(day21, part2)]
-fn solve_part2(input: &[GeneratorType]) -> String {
-    let mut map: HashMap<&str, Vec<&HashSet<String>>> = HashMap::new();
-    let mut ingridents = HashSet::new();
-    for label in input {
-        for allergen in label.allergens.iter() {
-            map.entry(allergen)
-                .or_insert(Vec::new())
-                .push(&label.ingridients);
-            for ing in label.ingridients.iter() {
-                ingridents.insert(ing.as_str());
-            }
-        }
+impl Solution {
+    /// The dangerous ingredients, ordered by their allergen.
+    pub fn dangerous_list(&self) -> Result<String, PlacementError> {
+        Ok(self.placement.result()?.values().join(","))
     }
 
-    let mut possible: HashMap<&str, HashSet<&str>> = HashMap::new();
-    for (allergen, ingredients) in map.iter() {
-        possible.insert(
-            allergen,
-            ingredients
-                .iter()
-                .cloned()
-                .flat_map(|a| a.iter().map(|s| s.as_str()))
-                .collect(),
-        );
+    /// Every deduction on its own line, followed by whatever is left open.
+    pub fn proof(&self) -> String {
+        let steps = self.steps.iter().map(|s| s.to_string());
+        let open = self.placement.unresolved.iter().map(|(a, c)| {
+            if c.is_empty() {
+                format!("{} has no ingredient left", a)
+            } else {
+                format!("{} is still one of {}", a, braced(c))
+            }
+        });
+        steps.chain(open).join("\n")
     }
 
-    let mut impossible: HashMap<&str, HashSet<&str>> = HashMap::new();
-    for (allergen, ingredients) in map.iter() {
-        impossible.insert(
-            allergen,
-            ingredients
+    pub fn to_json(&self) -> String {
+        let string = |s: &str| format!("\"{}\"", json_escape(s));
+        format!(
+            "{{\"steps\":[{}],\"assignment\":{{{}}},\"unresolved\":{{{}}}}}",
+            self.steps.iter().map(|s| s.to_json()).join(","),
+            self.placement
+                .assigned
                 .iter()
-                .cartesian_product(ingredients.iter())
-                .flat_map(|(a, b)| HashSet::symmetric_difference(a, b).map(|s| s.as_str()))
-                .collect(),
-        );
+                .map(|(a, i)| format!("{}:{}", string(a), string(i)))
+                .join(","),
+            self.placement
+                .unresolved
+                .iter()
+                .map(|(a, c)| format!("{}:[{}]", string(a), c.iter().map(|i| string(i)).join(",")))
+                .join(",")
+        )
     }
+}
 
-    let mut not_safe = HashSet::new();
-    for allergen in map.keys() {
-        let maybe = possible
-            .get(allergen)
-            .unwrap()
-            .difference(impossible.get(allergen).unwrap());
-        for m in maybe {
-            not_safe.insert(*m);
-        }
-    }
+pub struct AllergenSolver {
+    matcher: Matcher<String, String>,
+    safe: BTreeSet<String>,
+    initial: BTreeMap<String, BTreeSet<String>>,
+    steps: Vec<Deduction>,
+}
 
-    let mut a = map
-        .iter()
-        .map(|(allergen, words_sets)| {
-            (
-                *allergen,
-                not_safe
-                    .iter()
-                    .filter(|w| words_sets.iter().all(|s| s.contains(**w)))
-                    .copied()
-                    .collect::<HashSet<_>>(),
-            )
-        })
-        .collect::<HashMap<&str, HashSet<&str>>>();
-    let mut result: HashSet<(&str, &str)> = HashSet::new();
-    loop {
-        for x in result.iter() {
-            a.remove(x.0);
-            for (_, p) in a.iter_mut() {
-                p.remove(x.1);
+impl AllergenSolver {
+    /// An allergen can only be in the ingredients every label naming it shares.
+    pub fn new(labels: &[Label]) -> Self {
+        let mut candidates: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut named_by: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (idx, label) in labels.iter().enumerate() {
+            for allergen in label.allergens.iter() {
+                named_by.entry(allergen).or_default().push(idx + 1);
+                let ingridients = label.ingridients.iter().cloned().collect();
+                let shared = match candidates.get(allergen) {
+                    Some(before) => before.intersection(&ingridients).cloned().collect(),
+                    None => ingridients,
+                };
+                candidates.insert(allergen.clone(), shared);
             }
         }
-        if a.is_empty() {
-            break;
+        let safe = labels
+            .iter()
+            .flat_map(|l| l.ingridients.iter())
+            .filter(|i| !candidates.values().any(|c| c.contains(*i)))
+            .cloned()
+            .collect();
+        let steps = candidates
+            .iter()
+            .map(|(allergen, ingredients)| Deduction::Candidates {
+                allergen: allergen.clone(),
+                ingredients: ingredients.clone(),
+                labels: named_by[allergen.as_str()].clone(),
+            })
+            .collect();
+        AllergenSolver {
+            initial: candidates.clone(),
+            matcher: Matcher::new(candidates),
+            safe,
+            steps,
         }
-        for (allergen, possible) in a.iter() {
-            if possible.len() == 1 {
-                result.insert((allergen, possible.iter().last().unwrap()));
-            }
+    }
+
+    pub fn solve(self) -> Solution {
+        let AllergenSolver {
+            matcher,
+            safe,
+            initial,
+            mut steps,
+        } = self;
+        let placement = matcher.solve(
+            |_| None,
+            |m, step| {
+                steps.push(match step.clone() {
+                    Step::Assign(allergen, ingredient) => Deduction::Assigned {
+                        eliminated: m
+                            .assigned()
+                            .iter()
+                            .filter(|(_, i)| initial[&allergen].contains(*i))
+                            .map(|(by, i)| (i.clone(), by.clone()))
+                            .collect(),
+                        allergen,
+                        ingredient,
+                    },
+                    Step::RuleOut(allergen, ingredient) => Deduction::RuledOut {
+                        allergen,
+                        ingredient,
+                    },
+                })
+            },
+        );
+        Solution {
+            safe,
+            placement,
+            steps,
         }
     }
+}
 
-    let mut result = result.into_iter().collect::<Vec<_>>();
-    result.sort_by_key(|(k, _)| *k);
+#[aoc(day21, part1)]
+fn solve_part1(input: &[GeneratorType]) -> usize {
+    let solution = AllergenSolver::new(input).solve();
+    input
+        .iter()
+        .map(|l| {
+            l.ingridients
+                .iter()
+                .filter(|i| solution.safe.contains(*i))
+                .count()
+        })
+        .sum()
+}
 
-    result.iter().map(|(_, v)| v).join(",")
+#[aoc(day21, part2)]
+fn solve_part2(input: &[GeneratorType]) -> Result<String> {
+    Ok(AllergenSolver::new(input).solve().dangerous_list()?)
 }
 
 #[cfg(test)]
@@ -210,14 +333,111 @@ sqjhc fvjkl (contains soy)
 sqjhc mxmxvkd sbzzf (contains fish)";
 
     #[test]
-    fn test_part1() {
-        assert_eq!(solve_part1(generator(SAMPLE).as_slice()), 5);
+    fn test_part1() -> Result<()> {
+        assert_eq!(solve_part1(generator(SAMPLE)?.as_slice()), 5);
+        Ok(())
     }
     #[test]
-    fn test_part2() {
+    fn test_part2() -> Result<()> {
         assert_eq!(
-            solve_part2(generator(SAMPLE).as_slice()),
+            solve_part2(generator(SAMPLE)?.as_slice())?,
             "mxmxvkd,sqjhc,fvjkl"
         );
+        Ok(())
+    }
+
+    fn strings(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn solution() -> Result<()> {
+        let solution = AllergenSolver::new(&generator(SAMPLE)?).solve();
+        assert_eq!(solution.safe, strings(&["kfcds", "nhms", "sbzzf", "trh"]));
+        assert_eq!(solution.placement.assigned["fish"], "sqjhc");
+        assert!(solution.placement.unresolved.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn matching_fallback() -> Result<()> {
+        // Nothing has a single candidate, but dairy and fish use up a and b between them.
+        let labels = generator("a b (contains dairy, fish)\na b c (contains soy)")?;
+        let solution = AllergenSolver::new(&labels).solve();
+        assert_eq!(solution.placement.assigned["soy"], "c");
+        assert_eq!(
+            solution.placement.unresolved.keys().collect::<Vec<_>>(),
+            vec!["dairy", "fish"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn proof() -> Result<()> {
+        let solution = AllergenSolver::new(&generator(SAMPLE)?).solve();
+        assert_eq!(
+            solution.proof(),
+            "dairy ∈ {mxmxvkd} because of labels 1 and 2
+fish ∈ {mxmxvkd, sqjhc} because of labels 1 and 4
+soy ∈ {fvjkl, sqjhc} because of label 3
+dairy = mxmxvkd
+fish = sqjhc after eliminating mxmxvkd (dairy)
+soy = fvjkl after eliminating sqjhc (fish)"
+        );
+        let json = solution.to_json();
+        assert!(json.starts_with(
+            r#"{"steps":[{"kind":"candidates","allergen":"dairy","ingredients":["mxmxvkd"],"labels":[1,2]},"#
+        ));
+        assert!(json.contains(
+            r#"{"kind":"assigned","allergen":"fish","ingredient":"sqjhc","eliminated":{"mxmxvkd":"dairy"}}"#
+        ));
+        assert!(json.ends_with(
+            r#""assignment":{"dairy":"mxmxvkd","fish":"sqjhc","soy":"fvjkl"},"unresolved":{}}"#
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn proof_of_pruning() -> Result<()> {
+        let labels = generator("a b (contains dairy, fish)\na b c (contains soy)")?;
+        let solution = AllergenSolver::new(&labels).solve();
+        let proof = solution.proof();
+        assert!(proof.contains("soy ≠ a because the other allergens could not all be placed"));
+        // a and b were ruled out by matching, not taken by another allergen.
+        assert!(proof.contains("soy ≠ b because"));
+        assert!(proof.contains("\nsoy = c\n"));
+        assert!(proof.ends_with("fish is still one of {a, b}"));
+        assert!(solution
+            .to_json()
+            .contains(r#""unresolved":{"dairy":["a","b"],"fish":["a","b"]}"#));
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous() -> Result<()> {
+        let labels = generator("a b (contains dairy, fish)\na b c (contains fish)")?;
+        let solution = AllergenSolver::new(&labels).solve();
+        assert_eq!(solution.safe, strings(&["c"]));
+        assert_eq!(solution.placement.unresolved["dairy"], strings(&["a", "b"]));
+        match solution.dangerous_list() {
+            Err(PlacementError::Ambiguous(err)) => assert_eq!(err.unresolved.len(), 2),
+            other => panic!("expected an ambiguity, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn infeasible() -> Result<()> {
+        let labels = generator("a (contains dairy, fish)\nb c (contains soy)")?;
+        let solution = AllergenSolver::new(&labels).solve();
+        let err = solution.dangerous_list().unwrap_err();
+        assert_eq!(err, PlacementError::Infeasible(strings(&["fish"])));
+        assert_eq!(
+            err.to_string(),
+            "No assignment exists: nothing is left for fish"
+        );
+        assert!(solution.proof().contains("fish has no ingredient left"));
+        assert_eq!(solution.placement.unresolved["soy"], strings(&["b", "c"]));
+        Ok(())
     }
 }
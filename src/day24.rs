@@ -1,132 +1,220 @@
 use nom::{
-    branch::alt, bytes::complete::tag, combinator::recognize, error::Error, multi::many1, Finish,
-    IResult,
+    branch::alt,
+    bytes::complete::tag,
+    combinator::{all_consuming, map},
+    error::Error,
+    multi::fold_many1,
+    Finish, IResult,
 };
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap, HashSet},
-    hash::{Hash, Hasher},
+    collections::{HashMap, HashSet},
+    ops::{Add, Mul, Neg, Sub},
     str::FromStr,
 };
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
-}
-type GeneratorType = Tile;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Tile {
-    e: usize,
-    se: usize,
-    sw: usize,
-    w: usize,
-    nw: usize,
-    ne: usize,
-}
-
-macro_rules! dir {
-    ($s:ident, $d:ident, $set:ident) => {
-        let mut c = $s.clone();
-        c.$d += 1;
-        c.simplify();
-        $set.insert(c);
-    };
-}
-
-macro_rules! shorten {
-    ($tile:ident, $a:ident, $b:ident, $to:ident) => {
-        if $tile.$a > 0 && $tile.$b > 0 {
-            let shift = $tile.$a.min($tile.$b);
-            $tile.$a -= shift;
-            $tile.$b -= shift;
-            $tile.$to += shift;
-        }
-    };
+
+type GeneratorType = Hex;
+
+/// The six neighbours of a pointy-topped hex, clockwise from east.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    E,
+    SE,
+    SW,
+    W,
+    NW,
+    NE,
 }
 
-macro_rules! cancel {
-    ($tile:ident, $a:ident, $b:ident) => {
-        if $tile.$a > 0 && $tile.$b > 0 {
-            let shift = $tile.$a.min($tile.$b);
-            $tile.$a -= shift;
-            $tile.$b -= shift;
-        }
-    };
-}
-
-impl Tile {
-    fn simplify(&mut self) {
-        let mut hash = 0;
-        while hash != calculate_hash(self) {
-            hash = calculate_hash(self);
-            shorten!(self, nw, sw, w);
-            shorten!(self, ne, se, e);
-            shorten!(self, w, ne, nw);
-            shorten!(self, w, se, sw);
-            shorten!(self, e, nw, ne);
-            shorten!(self, e, sw, se);
-            cancel!(self, e, w);
-            cancel!(self, ne, sw);
-            cancel!(self, nw, se);
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::E,
+        Direction::SE,
+        Direction::SW,
+        Direction::W,
+        Direction::NW,
+        Direction::NE,
+    ];
+
+    pub fn vector(self) -> Hex {
+        match self {
+            Direction::E => Hex::new(1, 0),
+            Direction::SE => Hex::new(0, 1),
+            Direction::SW => Hex::new(-1, 1),
+            Direction::W => Hex::new(-1, 0),
+            Direction::NW => Hex::new(0, -1),
+            Direction::NE => Hex::new(1, -1),
         }
     }
 
-    fn adjacent(&self) -> HashSet<Self> {
-        let mut set = HashSet::new();
-        dir!(self, nw, set);
-        dir!(self, ne, set);
-        dir!(self, w, set);
-        dir!(self, e, set);
-        dir!(self, sw, set);
-        dir!(self, se, set);
+    /// The direction `sixths` turns of 60° clockwise from this one.
+    pub fn turn(self, sixths: i32) -> Direction {
+        let idx = Direction::ALL.iter().position(|d| *d == self).unwrap() as i32;
+        Direction::ALL[(idx + sixths).rem_euclid(6) as usize]
+    }
+}
+
+/// Axial coordinates with `r` growing southwards; the cube `s` is implied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hex {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl Add for Hex {
+    type Output = Hex;
+
+    fn add(self, other: Hex) -> Hex {
+        Hex::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for Hex {
+    type Output = Hex;
+
+    fn sub(self, other: Hex) -> Hex {
+        Hex::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Neg for Hex {
+    type Output = Hex;
+
+    fn neg(self) -> Hex {
+        Hex::new(-self.q, -self.r)
+    }
+}
+
+impl Mul<i32> for Hex {
+    type Output = Hex;
 
-        set
+    fn mul(self, k: i32) -> Hex {
+        Hex::new(self.q * k, self.r * k)
     }
 }
 
-impl Default for Tile {
-    fn default() -> Self {
-        Tile {
-            e: 0,
-            se: 0,
-            sw: 0,
-            w: 0,
-            nw: 0,
-            ne: 0,
+impl Hex {
+    pub const ORIGIN: Hex = Hex { q: 0, r: 0 };
+
+    pub fn new(q: i32, r: i32) -> Self {
+        Hex { q, r }
+    }
+
+    fn from_cube(q: i32, r: i32, s: i32) -> Self {
+        debug_assert_eq!(q + r + s, 0);
+        Hex { q, r }
+    }
+
+    pub fn s(self) -> i32 {
+        -self.q - self.r
+    }
+
+    pub fn neighbour(self, direction: Direction) -> Hex {
+        self + direction.vector()
+    }
+
+    pub fn neighbours(self) -> [Hex; 6] {
+        let mut out = [self; 6];
+        for (n, d) in out.iter_mut().zip(Direction::ALL.iter()) {
+            *n = self.neighbour(*d);
+        }
+        out
+    }
+
+    /// Steps from the origin.
+    pub fn length(self) -> u32 {
+        ((self.q.abs() + self.r.abs() + self.s().abs()) / 2) as u32
+    }
+
+    pub fn distance(self, other: Hex) -> u32 {
+        (self - other).length()
+    }
+
+    /// The hexes exactly `radius` steps away, clockwise from the eastern corner.
+    pub fn ring(self, radius: u32) -> impl Iterator<Item = Hex> {
+        let edge = radius.max(1);
+        let count = if radius == 0 { 1 } else { 6 * radius };
+        (0..count).map(move |k| {
+            let side = Direction::ALL[(k / edge) as usize];
+            let corner = self + side.vector() * radius as i32;
+            corner + side.turn(2).vector() * (k % edge) as i32
+        })
+    }
+
+    /// The hexes within `radius` steps, ring by ring outwards.
+    pub fn spiral(self, radius: u32) -> impl Iterator<Item = Hex> {
+        (0..=radius).flat_map(move |k| self.ring(k))
+    }
+
+    /// Turns `sixths` of 60° clockwise about the origin.
+    pub fn rotate(self, sixths: i32) -> Hex {
+        (0..sixths.rem_euclid(6)).fold(self, |h, _| Hex::from_cube(-h.r, -h.s(), -h.q))
+    }
+
+    pub fn rotate_around(self, centre: Hex, sixths: i32) -> Hex {
+        (self - centre).rotate(sixths) + centre
+    }
+
+    /// Mirrors across the line through the origin where `q` is fixed, swapping `r` and `s`.
+    pub fn reflect_q(self) -> Hex {
+        Hex::from_cube(self.q, self.s(), self.r)
+    }
+
+    /// Mirrors across the line through the origin where `r` is fixed, swapping `q` and `s`.
+    pub fn reflect_r(self) -> Hex {
+        Hex::from_cube(self.s(), self.r, self.q)
+    }
+
+    /// Mirrors across the line through the origin where `s` is fixed, swapping `q` and `r`.
+    pub fn reflect_s(self) -> Hex {
+        Hex::from_cube(self.r, self.q, self.s())
+    }
+
+    /// Centre of the hex in screen space (y down) for hexes of circumradius `size`.
+    pub fn to_pixel(self, size: f64) -> (f64, f64) {
+        let (q, r) = (self.q as f64, self.r as f64);
+        let x = size * 3f64.sqrt() * (q + r / 2.0);
+        let y = size * 1.5 * r;
+        (x, y)
+    }
+
+    /// The hex containing the point, the inverse of `to_pixel`.
+    pub fn from_pixel(x: f64, y: f64, size: f64) -> Hex {
+        let q = (3f64.sqrt() / 3.0 * x - y / 3.0) / size;
+        let r = (2.0 / 3.0 * y) / size;
+        let s = -q - r;
+        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
         }
+        Hex::new(rq as i32, rr as i32)
     }
 }
 
-fn parse_directions(s: &str) -> IResult<&str, Vec<&str>> {
-    let (i, dirs) = many1(recognize(alt((
-        tag("e"),
-        tag("se"),
-        tag("sw"),
-        tag("w"),
-        tag("nw"),
-        tag("ne"),
-    ))))(s)?;
+fn direction(s: &str) -> IResult<&str, Direction> {
+    alt((
+        map(tag("e"), |_| Direction::E),
+        map(tag("se"), |_| Direction::SE),
+        map(tag("sw"), |_| Direction::SW),
+        map(tag("w"), |_| Direction::W),
+        map(tag("nw"), |_| Direction::NW),
+        map(tag("ne"), |_| Direction::NE),
+    ))(s)
+}
 
-    Ok((i, dirs))
+fn parse_path(s: &str) -> IResult<&str, Hex> {
+    all_consuming(fold_many1(direction, Hex::ORIGIN, Hex::neighbour))(s)
 }
 
-impl FromStr for Tile {
+impl FromStr for Hex {
     type Err = Error<String>;
 
+    /// Walks a run of directions like `nwwswee` from the origin.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match parse_directions(s).finish() {
-            Ok((_remaining, dirs)) => Ok(dirs.iter().fold(Tile::default(), |mut tile, dir| {
-                match *dir {
-                    "e" => tile.e += 1,
-                    "se" => tile.se += 1,
-                    "sw" => tile.sw += 1,
-                    "w" => tile.w += 1,
-                    "nw" => tile.nw += 1,
-                    "ne" => tile.ne += 1,
-                    _ => unreachable!("By problem statement"),
-                };
-                tile
-            })),
+        match parse_path(s).finish() {
+            Ok((_remaining, hex)) => Ok(hex),
             Err(Error { input, code }) => Err(Error {
                 input: input.to_string(),
                 code,
@@ -139,41 +227,36 @@ impl FromStr for Tile {
 fn generator(input: &str) -> Vec<GeneratorType> {
     input
         .split('\n')
-        .map(|tile| tile.parse::<Tile>().unwrap())
+        .map(|tile| tile.parse::<Hex>().unwrap())
         .collect()
 }
 
-#[aoc(day24, part1)]
-fn solve_part1(input: &[GeneratorType]) -> usize {
+fn flipped(input: &[GeneratorType]) -> HashSet<Hex> {
     let mut map = HashMap::new();
-    for mut tile in input.to_vec().into_iter() {
-        tile.simplify();
-        let count = map.entry(tile).or_insert(0);
+    for tile in input.iter() {
+        let count = map.entry(*tile).or_insert(0);
         *count += 1;
     }
+    map.into_iter()
+        .filter(|(_, c)| *c % 2 == 1)
+        .map(|(t, _)| t)
+        .collect()
+}
 
-    map.iter().filter(|(_, c)| *c % 2 == 1).count()
+#[aoc(day24, part1)]
+fn solve_part1(input: &[GeneratorType]) -> usize {
+    flipped(input).len()
 }
 
 #[aoc(day24, part2)]
 fn solve_part2(input: &[GeneratorType]) -> usize {
-    let mut map = HashMap::new();
-    for mut tile in input.to_vec().into_iter() {
-        tile.simplify();
-        let count = map.entry(tile).or_insert(0);
-        *count += 1;
-    }
-    let mut on = map
-        .into_iter()
-        .filter(|(_, c)| *c % 2 == 1)
-        .map(|(t, _)| t)
-        .collect::<HashSet<_>>();
+    let mut on = flipped(input);
 
-    for i in 0..100 {
+    for _ in 0..100 {
         let mut tiles = HashMap::new();
         for tile in on.iter() {
-            for adj in tile.adjacent() {
-                let count = tiles.entry(adj).or_insert(0);
+            for adj in tile.neighbours().iter() {
+                let count = tiles.entry(*adj).or_insert(0);
                 *count += 1;
             }
         }
@@ -226,4 +309,65 @@ wseweeenwnesenwwwswnew";
     fn test_part2() {
         assert_eq!(solve_part2(generator(SAMPLE).as_slice()), 2208);
     }
+
+    #[test]
+    fn parse() {
+        assert_eq!("esew".parse::<Hex>().unwrap(), Hex::new(0, 1));
+        assert_eq!("nwwswee".parse::<Hex>().unwrap(), Hex::ORIGIN);
+        assert!("nwx".parse::<Hex>().is_err());
+        assert!("".parse::<Hex>().is_err());
+    }
+
+    #[test]
+    fn distance() {
+        let a = Hex::new(3, -1);
+        assert_eq!(a.length(), 3);
+        assert_eq!(a.distance(Hex::new(-2, 2)), 5);
+        assert!(Hex::ORIGIN.neighbours().iter().all(|n| n.length() == 1));
+        let sum = Direction::ALL
+            .iter()
+            .fold(Hex::ORIGIN, |h, d| h.neighbour(*d));
+        assert_eq!(sum, Hex::ORIGIN);
+    }
+
+    #[test]
+    fn rings() {
+        let centre = Hex::new(2, -5);
+        assert_eq!(centre.ring(0).collect::<Vec<_>>(), vec![centre]);
+        for radius in 1..5 {
+            let ring: HashSet<_> = centre.ring(radius).collect();
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|h| h.distance(centre) == radius));
+        }
+        assert_eq!(centre.ring(1).next(), Some(centre.neighbour(Direction::E)));
+        let spiral: HashSet<_> = centre.spiral(3).collect();
+        assert_eq!(spiral.len(), 37);
+    }
+
+    #[test]
+    fn rotation_and_reflection() {
+        let h = Hex::new(3, -1);
+        assert_eq!(Direction::E.vector().rotate(1), Direction::SE.vector());
+        assert_eq!(h.rotate(6), h);
+        assert_eq!(h.rotate(-1), h.rotate(5));
+        assert_eq!(h.rotate(2).length(), h.length());
+        assert_eq!(h.rotate_around(h, 3), h);
+        assert_eq!(Direction::NE.turn(1), Direction::E);
+        for reflect in [Hex::reflect_q, Hex::reflect_r, Hex::reflect_s].iter() {
+            assert_eq!(reflect(reflect(h)), h);
+        }
+        assert_eq!(Direction::E.vector().reflect_q(), Direction::NE.vector());
+        assert_eq!(Direction::E.vector().reflect_r(), Direction::W.vector());
+        assert_eq!(h.reflect_q().q, h.q);
+    }
+
+    #[test]
+    fn pixels() {
+        for h in Hex::ORIGIN.spiral(4) {
+            let (x, y) = h.to_pixel(10.0);
+            assert_eq!(Hex::from_pixel(x, y, 10.0), h);
+            assert_eq!(Hex::from_pixel(x + 4.0, y - 4.0, 10.0), h);
+        }
+        assert_eq!(Hex::new(0, 2).to_pixel(1.0), (3f64.sqrt(), 3.0));
+    }
 }
@@ -1,28 +1,30 @@
-mod day01;
-mod day02;
-mod day03;
-mod day04;
-mod day05;
-mod day06;
-mod day07;
+pub mod day01;
+pub mod day02;
+pub mod day03;
+pub mod day04;
+pub mod day05;
+pub mod day06;
+pub mod day07;
 mod day08;
-mod day09;
-mod day10;
-mod day11;
-mod day12;
+pub mod day09;
+pub mod day10;
+pub mod day11;
+pub mod day12;
 mod day13;
-mod day14;
-mod day15;
-mod day16;
-mod day17;
+pub mod day14;
+pub mod day15;
+pub mod day16;
+pub mod day17;
 mod day18;
 mod day19;
 mod day20;
-mod day21;
+pub mod day21;
 mod day22;
 mod day23;
-mod day24;
+pub mod day24;
 mod day25;
+mod escape;
+pub mod matching;
 
 #[macro_use]
 extern crate aoc_runner_derive;
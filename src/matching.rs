@@ -0,0 +1,332 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    hash::Hash,
+};
+
+/// Whether every set can be given an element of its own, never `except`.
+///
+/// Kuhn's augmenting paths: each set takes a free element, or bumps the
+/// owner of a taken one onto another of its elements.
+pub fn saturates<T: Eq + Hash>(sets: &[&BTreeSet<T>], except: &T) -> bool {
+    fn augment<'a, T: Eq + Hash>(
+        set: usize,
+        sets: &[&'a BTreeSet<T>],
+        except: &T,
+        owner: &mut HashMap<&'a T, usize>,
+        seen: &mut HashSet<&'a T>,
+    ) -> bool {
+        for item in sets[set].iter() {
+            if item != except && seen.insert(item) {
+                let free = match owner.get(item) {
+                    None => true,
+                    Some(other) => augment(*other, sets, except, owner, seen),
+                };
+                if free {
+                    owner.insert(item, set);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    let mut owner = HashMap::new();
+    (0..sets.len()).all(|s| augment(s, sets, except, &mut owner, &mut HashSet::new()))
+}
+
+/// What was left when the keys could not all be pinned to a single value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ambiguity<K, V> {
+    pub assigned: BTreeMap<K, V>,
+    pub unresolved: BTreeMap<K, BTreeSet<V>>,
+}
+
+impl<K: fmt::Display, V: fmt::Debug> fmt::Display for Ambiguity<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not place {}:", self.unresolved.len())?;
+        for (key, values) in self.unresolved.iter() {
+            write!(f, " {} -> {:?};", key, values)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: fmt::Display + fmt::Debug, V: fmt::Debug> std::error::Error for Ambiguity<K, V> {}
+
+/// Why the keys could not be pinned down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlacementError<K, V> {
+    /// More than one assignment fits.
+    Ambiguous(Ambiguity<K, V>),
+    /// No assignment fits: these keys have no value left.
+    Infeasible(BTreeSet<K>),
+}
+
+impl<K: fmt::Display, V: fmt::Debug> fmt::Display for PlacementError<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlacementError::Ambiguous(ambiguity) => ambiguity.fmt(f),
+            PlacementError::Infeasible(keys) => {
+                let keys: Vec<_> = keys.iter().map(|k| k.to_string()).collect();
+                write!(
+                    f,
+                    "No assignment exists: nothing is left for {}",
+                    keys.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl<K: fmt::Display + fmt::Debug, V: fmt::Debug> std::error::Error for PlacementError<K, V> {}
+
+/// Everything a `Matcher` pinned, and what the rest could still be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement<K, V> {
+    pub assigned: BTreeMap<K, V>,
+    pub unresolved: BTreeMap<K, BTreeSet<V>>,
+}
+
+impl<K: Ord + Clone, V: Clone> Placement<K, V> {
+    /// The full assignment, or why there isn't one.
+    pub fn result(&self) -> Result<&BTreeMap<K, V>, PlacementError<K, V>> {
+        let stuck: BTreeSet<_> = self
+            .unresolved
+            .iter()
+            .filter(|(_, values)| values.is_empty())
+            .map(|(k, _)| k.clone())
+            .collect();
+        if !stuck.is_empty() {
+            return Err(PlacementError::Infeasible(stuck));
+        }
+        if !self.unresolved.is_empty() {
+            return Err(PlacementError::Ambiguous(Ambiguity {
+                assigned: self.assigned.clone(),
+                unresolved: self.unresolved.clone(),
+            }));
+        }
+        Ok(&self.assigned)
+    }
+}
+
+/// A step the `Matcher` took, reported before it is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step<K, V> {
+    Assign(K, V),
+    /// No complete assignment gives the key this value.
+    RuleOut(K, V),
+}
+
+/// Pins each key to a value of its own out of its candidates.
+///
+/// Keys with one candidate left are assigned, and that value is taken from every
+/// other key. When that stalls, candidates no complete matching can use are pruned.
+#[derive(Debug, Clone)]
+pub struct Matcher<K, V> {
+    candidates: BTreeMap<K, BTreeSet<V>>,
+    assigned: BTreeMap<K, V>,
+}
+
+impl<K: Ord + Clone, V: Ord + Clone + Hash> Matcher<K, V> {
+    pub fn new(candidates: BTreeMap<K, BTreeSet<V>>) -> Self {
+        Matcher {
+            candidates,
+            assigned: BTreeMap::new(),
+        }
+    }
+
+    pub fn assigned(&self) -> &BTreeMap<K, V> {
+        &self.assigned
+    }
+
+    pub fn candidates(&self, key: &K) -> &BTreeSet<V> {
+        &self.candidates[key]
+    }
+
+    /// Keys that don't have a value yet.
+    pub fn open(&self) -> Vec<K> {
+        self.candidates
+            .keys()
+            .filter(|k| !self.assigned.contains_key(*k))
+            .cloned()
+            .collect()
+    }
+
+    fn assign(&mut self, key: &K, value: &V) {
+        self.assigned.insert(key.clone(), value.clone());
+        for (other, candidates) in self.candidates.iter_mut() {
+            if other == key {
+                *candidates = std::iter::once(value.clone()).collect();
+            } else {
+                candidates.remove(value);
+            }
+        }
+    }
+
+    fn naked_single(&self) -> Option<(K, V)> {
+        self.open()
+            .into_iter()
+            .find(|k| self.candidates[k].len() == 1)
+            .map(|k| {
+                let value = self.candidates[&k].iter().next().unwrap().clone();
+                (k, value)
+            })
+    }
+
+    fn propagate(
+        &mut self,
+        single: &mut impl FnMut(&Self) -> Option<(K, V)>,
+        record: &mut impl FnMut(&Self, &Step<K, V>),
+    ) {
+        while let Some((key, value)) = self.naked_single().or_else(|| single(self)) {
+            record(self, &Step::Assign(key.clone(), value.clone()));
+            self.assign(&key, &value);
+        }
+    }
+
+    // Whether the other open keys can still all be placed once `key` takes `value`.
+    fn has_matching(&self, key: &K, value: &V) -> bool {
+        let others: Vec<_> = self
+            .open()
+            .into_iter()
+            .filter(|k| k != key)
+            .map(|k| &self.candidates[&k])
+            .collect();
+        saturates(&others, value)
+    }
+
+    fn prune_unmatchable(&mut self, record: &mut impl FnMut(&Self, &Step<K, V>)) -> bool {
+        // With a key already out of values no matching exists, and pruning would empty the rest.
+        if self.open().iter().any(|k| self.candidates[k].is_empty()) {
+            return false;
+        }
+        let mut pruned = false;
+        for key in self.open() {
+            let viable: BTreeSet<V> = self.candidates[&key]
+                .iter()
+                .filter(|v| self.has_matching(&key, v))
+                .cloned()
+                .collect();
+            if viable.len() < self.candidates[&key].len() {
+                for value in self.candidates[&key].difference(&viable) {
+                    record(self, &Step::RuleOut(key.clone(), value.clone()));
+                }
+                self.candidates.insert(key, viable);
+                pruned = true;
+            }
+        }
+        pruned
+    }
+
+    /// Runs to a fixed point. `single` can offer extra forced assignments the
+    /// caller knows about, and `record` sees every step before it happens.
+    pub fn solve(
+        mut self,
+        mut single: impl FnMut(&Self) -> Option<(K, V)>,
+        mut record: impl FnMut(&Self, &Step<K, V>),
+    ) -> Placement<K, V> {
+        self.propagate(&mut single, &mut record);
+        while !self.open().is_empty() && self.prune_unmatchable(&mut record) {
+            self.propagate(&mut single, &mut record);
+        }
+        let unresolved = self
+            .open()
+            .into_iter()
+            .map(|k| {
+                let candidates = self.candidates[&k].clone();
+                (k, candidates)
+            })
+            .collect();
+        Placement {
+            assigned: self.assigned,
+            unresolved,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sets(sets: &[&[u32]]) -> Vec<BTreeSet<u32>> {
+        sets.iter().map(|s| s.iter().cloned().collect()).collect()
+    }
+
+    #[test]
+    fn augmenting() {
+        // The first set has to give up 1 for the second to fit.
+        let owned = sets(&[&[1, 2], &[1], &[2, 3]]);
+        let refs: Vec<_> = owned.iter().collect();
+        assert!(saturates(&refs, &0));
+        assert!(!saturates(&refs, &3));
+        assert!(!saturates(&refs, &1));
+    }
+
+    #[test]
+    fn too_few() {
+        let owned = sets(&[&[1, 2], &[1, 2], &[2, 1]]);
+        let refs: Vec<_> = owned.iter().collect();
+        assert!(!saturates(&refs, &0));
+        assert!(saturates(&refs[..0], &0));
+    }
+
+    fn matcher(sets: &[(&'static str, &[u32])]) -> Matcher<&'static str, u32> {
+        Matcher::new(
+            sets.iter()
+                .map(|(k, vs)| (*k, vs.iter().cloned().collect()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn singles_then_pruning() {
+        // c is a naked single; after that a and b use up 1 and 2, leaving d with 4.
+        let mut steps = vec![];
+        let placement = matcher(&[
+            ("a", &[1, 2]),
+            ("b", &[1, 2]),
+            ("c", &[3]),
+            ("d", &[1, 3, 4]),
+        ])
+        .solve(|_| None, |_, step| steps.push(step.clone()));
+        assert_eq!(placement.assigned[&"c"], 3);
+        assert_eq!(placement.assigned[&"d"], 4);
+        assert_eq!(placement.unresolved.len(), 2);
+        assert_eq!(
+            steps,
+            vec![
+                Step::Assign("c", 3),
+                Step::RuleOut("d", 1),
+                Step::Assign("d", 4)
+            ]
+        );
+        assert!(matches!(
+            placement.result(),
+            Err(PlacementError::Ambiguous(_))
+        ));
+    }
+
+    #[test]
+    fn extra_singles() {
+        let placement = matcher(&[("a", &[1, 2]), ("b", &[1, 2])])
+            .solve(|m| m.open().first().map(|k| (*k, 2)), |_, _| {});
+        assert_eq!(placement.result().unwrap().get("b"), Some(&1));
+    }
+
+    #[test]
+    fn infeasible() {
+        let placement =
+            matcher(&[("a", &[1]), ("b", &[1]), ("c", &[2, 3])]).solve(|_| None, |_, _| {});
+        let err = placement.result().unwrap_err();
+        assert_eq!(
+            err,
+            PlacementError::Infeasible(std::iter::once("b").collect())
+        );
+        assert_eq!(
+            err.to_string(),
+            "No assignment exists: nothing is left for b"
+        );
+        assert_eq!(placement.unresolved[&"c"].len(), 2);
+    }
+}